@@ -0,0 +1,26 @@
+//
+// Git commit and build time for the version log line and /version, as AWGW_GIT_COMMIT and AWGW_BUILD_TIME
+//
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    // Docker builds don't copy .git, pass the commit as AWGW_GIT_COMMIT instead
+    let commit = std::env::var("AWGW_GIT_COMMIT").ok().filter(|commit| !commit.is_empty()).or_else(|| {
+        Command::new("git").args(["rev-parse", "--short=12", "HEAD"]).output().ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|commit| commit.trim().to_string())
+    }).unwrap_or("unknown".to_string());
+
+    // Unix seconds, SOURCE_DATE_EPOCH for reproducible builds
+    let build_time = std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs()));
+
+    println!("cargo:rustc-env=AWGW_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=AWGW_BUILD_TIME={}", build_time);
+    println!("cargo:rerun-if-env-changed=AWGW_GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
@@ -0,0 +1,123 @@
+//
+// Simulated gateway answering commands with canned responses from a json fixture, for testing
+// without hardware. The fixture maps command ids to hex payloads:
+//   { "0x27": "01 00 E6 07 37", "0x50": "04 56 31 2E 30" }
+//
+use std::{collections::HashMap, io::{Read, Write}, net::{TcpListener, TcpStream}};
+use clap::Parser;
+use aw_gateway_rs::transport::MockTransport;
+
+#[derive(Parser, Debug)]
+#[command(about = "Simulated Ecowitt gateway serving canned responses")]
+struct Args {
+    #[arg(help = "Json file mapping command ids to hex payloads")]
+    fixture: String,
+
+    #[arg(long, default_value_t = 45000, help = "Port to listen on")]
+    port: u16,
+}
+
+fn parse_hex(value: &str) -> Result<Vec<u8>, String> {
+    let digits: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("Odd number of hex digits in {:?}", value));
+    }
+
+    (0..digits.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|e| format!("Invalid hex {:?} - {}", value, e)))
+        .collect()
+}
+
+fn load_fixture(path: &str) -> Result<HashMap<u8, Vec<u8>>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {} - {}", path, e))?;
+    let entries: HashMap<String, String> = serde_json::from_str(&content).map_err(|e| format!("Failed to parse {} - {}", path, e))?;
+
+    entries.iter().map(|(cmd, payload)| {
+        let id = u8::from_str_radix(cmd.trim_start_matches("0x"), 16).map_err(|e| format!("Invalid command id {:?} - {}", cmd, e))?;
+        Ok((id, MockTransport::frame(id, &parse_hex(payload)?)))
+    }).collect()
+}
+
+fn serve(mut stream: TcpStream, responses: &HashMap<u8, Vec<u8>>) {
+    let mut packet = [0u8; 256];
+    // The client may send several commands on one connection
+    loop {
+        let n = match stream.read(&mut packet) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+
+        if n < 3 || packet[0..2] != [0xFF, 0xFF] {
+            log::warn!("Ignoring malformed packet {:?}", &packet[..n]);
+            return;
+        }
+
+        let cmd = packet[2];
+        let Some(response) = responses.get(&cmd) else {
+            log::warn!("No response for command {:#x}, closing connection", cmd);
+            return;
+        };
+
+        log::debug!("Answering command {:#x} with {:?}", cmd, response);
+        if let Err(e) = stream.write_all(response) {
+            log::warn!("Failed to send response - {:?}", e);
+            return;
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let _log_handle = flexi_logger::Logger::try_with_env_or_str("info")?.start()?;
+    let args = Args::parse();
+
+    let responses = load_fixture(&args.fixture)?;
+    let listener = TcpListener::bind(("0.0.0.0", args.port))?;
+    log::info!("Serving {} commands from {} on port {}", responses.len(), args.fixture, args.port);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => serve(stream, &responses),
+            Err(e) => log::warn!("Failed to accept connection - {:?}", e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aw_gateway_rs::gateway::{SensorGateway, SensorValue};
+
+    #[test_log::test]
+    fn gateway_reads_from_mock() {
+        let fixture = r#"{
+            "0x26": "AA BB CC DD EE FF",
+            "0x27": "01 00 D7 06 37 02 FF CC",
+            "0x30": "01 01 65 A8 D0 40 27 01",
+            "0x3c": "00 00 00 00 C4 00 04",
+            "0x50": "0E 47 57 31 30 30 30 43 5F 56 31 2E 37 2E 37"
+        }"#;
+        let path = std::env::temp_dir().join(format!("aw-gateway-rs-{}-mock-gateway.json", std::process::id()));
+        std::fs::write(&path, fixture).unwrap();
+        let responses = load_fixture(path.to_str().unwrap()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                serve(stream, &responses);
+            }
+        });
+
+        let gateway = SensorGateway::new("127.0.0.1".to_string(), port).unwrap();
+        assert_eq!(gateway.firmware(), "GW1000C_V1.7.7");
+
+        let reading = gateway.poll().unwrap();
+        let values: Vec<(&str, Option<f64>)> = reading.sensors().map(|sensor| (sensor.name(), sensor.value().as_f64())).collect();
+        assert_eq!(values, vec![("indoor_temp", Some(21.5)), ("in_humidity", Some(55.0)), ("outdoor_temp", Some(-5.2))]);
+        assert!(matches!(reading.sensors().nth(1).unwrap().value(), SensorValue::Humidity(_)));
+        assert_eq!(reading.metadata.len(), 1);
+        assert_eq!(reading.metadata.values().next().unwrap().type_id_str, "wh65");
+    }
+}
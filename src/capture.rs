@@ -0,0 +1,86 @@
+//
+// Capture of raw gateway responses, to attach to bug reports about decoding
+//
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::gateway::SensorGateway;
+
+// Size at which the capture file is rotated
+const DEFAULT_MAX_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug)]
+pub struct FrameCapture {
+    path: PathBuf,
+    max_size: u64,
+    // Opened on the first captured frame
+    file: Mutex<Option<File>>,
+}
+
+impl FrameCapture {
+    // Capture to `<dir>/capture_<name>.txt`, the previous file is kept as `.txt.1` on rotation
+    pub fn new(dir: &Path, name: &str) -> Result<Self, String> {
+        std::fs::create_dir_all(dir).map_err(|err| format!("Failed to create capture directory {:?} - {}", dir, err))?;
+        Ok(FrameCapture {
+            path: dir.join(format!("capture_{}.txt", name)),
+            max_size: DEFAULT_MAX_SIZE,
+            file: Mutex::new(None),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Append one line `<timestamp> <command id> <frame hex>`, failures are only logged
+    pub fn record(&self, cmd: u8, frame: &[u8]) {
+        if let Err(err) = self.write(cmd, frame) {
+            log::warn!("Failed to capture response to command {:#04x} in {:?} - {}", cmd, self.path, err);
+        }
+    }
+
+    fn write(&self, cmd: u8, frame: &[u8]) -> std::io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        let current = match file.take() {
+            Some(current) if current.metadata()?.len() < self.max_size => current,
+            Some(_) => {
+                std::fs::rename(&self.path, self.path.with_extension("txt.1"))?;
+                self.open()?
+            }
+            None => self.open()?,
+        };
+
+        let current = file.insert(current);
+        writeln!(current, "{} {:02X} {}", chrono::Utc::now().to_rfc3339(), cmd, SensorGateway::bytes_to_hex(frame, " "))
+    }
+
+    fn open(&self) -> std::io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn writes_frames_as_hex() {
+        let dir = std::env::temp_dir().join(format!("aw-gateway-rs-{}-capture", std::process::id()));
+        let mut capture = FrameCapture::new(&dir, "gw1").unwrap();
+        capture.max_size = 40;
+
+        capture.record(0x50, &[0xFF, 0xFF, 0x50, 0x04, 0x56, 0xAA]);
+        let content = std::fs::read_to_string(capture.path()).unwrap();
+        let (time, frame) = content.trim_end().split_once(' ').unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(time).is_ok());
+        assert_eq!(frame, "50 FF FF 50 04 56 AA");
+
+        // Past max_size the file moves to .txt.1
+        capture.record(0x27, &[0xFF, 0xFF, 0x27]);
+        assert_eq!(std::fs::read_to_string(capture.path().with_extension("txt.1")).unwrap(), content);
+        assert!(std::fs::read_to_string(capture.path()).unwrap().trim_end().ends_with(" 27 FF FF 27"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
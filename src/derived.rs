@@ -0,0 +1,121 @@
+//
+// Values computed from other live data fields
+//
+use std::str::FromStr;
+
+use crate::gateway::{SensorData, SensorValue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Derivation {
+    DewPoint,
+    // 16-point compass direction of wind_dir
+    WindCardinal,
+}
+
+impl FromStr for Derivation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dew_point" => Ok(Derivation::DewPoint),
+            "wind_cardinal" => Ok(Derivation::WindCardinal),
+            _ => Err(format!("Unknown derived value {:?}, expected dew_point or wind_cardinal", s)),
+        }
+    }
+}
+
+impl Derivation {
+    // Name of the field the derivation adds to the live data
+    pub fn field_name(&self) -> &'static str {
+        match self {
+            Derivation::DewPoint => "dew_point",
+            Derivation::WindCardinal => "wind_cardinal",
+        }
+    }
+}
+
+// Magnus formula, temperature in °C and relative humidity in %
+pub fn dew_point(temp: f64, humidity: f64) -> Option<f64> {
+    if humidity <= 0.0 || humidity > 100.0 {
+        return None;
+    }
+
+    const B: f64 = 17.62;
+    const C: f64 = 243.12;
+    let gamma = (humidity / 100.0).ln() + B * temp / (C + temp);
+    Some(C * gamma / (B - gamma))
+}
+
+const COMPASS_POINTS: [&str; 16] = ["N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW", "NW", "NNW"];
+
+// Each point covers 22.5° centered on it, so 349° to 11° is N
+pub fn compass_point(degrees: f64) -> &'static str {
+    let index = (degrees.rem_euclid(360.0) / 22.5).round() as usize % COMPASS_POINTS.len();
+    COMPASS_POINTS[index]
+}
+
+fn find_value(data: &[Vec<SensorData>], field: &str) -> Option<f64> {
+    match data.iter().flatten().find(|sensor| sensor.name() == field)?.value() {
+        SensorValue::Temp(val) | SensorValue::Humidity(val) => Some(*val),
+        SensorValue::Direction(val) => Some(*val as f64),
+        _ => None,
+    }
+}
+
+// Append the requested derived values to the live data, skipping those missing an input
+pub fn apply(data: &mut Vec<Vec<SensorData>>, derivations: &[Derivation]) {
+    for derivation in derivations {
+        match derivation {
+            Derivation::DewPoint => {
+                let temp = find_value(data, "outdoor_temp");
+                let humidity = find_value(data, "out_humidity");
+                if let Some(dew_point) = temp.zip(humidity).and_then(|(temp, humidity)| dew_point(temp, humidity)) {
+                    data.push(vec![SensorData::new(derivation.field_name(), SensorValue::Temp(dew_point))]);
+                }
+            }
+            Derivation::WindCardinal => {
+                if let Some(degrees) = find_value(data, "wind_dir") {
+                    data.push(vec![SensorData::new(derivation.field_name(), SensorValue::Compass(compass_point(degrees)))]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn dew_point_of_known_values() {
+        let close = |value: Option<f64>, expected: f64| value.is_some_and(|value| (value - expected).abs() < 0.1);
+
+        assert!(close(dew_point(20.0, 50.0), 9.3));
+        assert!(close(dew_point(25.0, 80.0), 21.3));
+        assert!(close(dew_point(-5.0, 100.0), -5.0));
+        assert_eq!(dew_point(20.0, 0.0), None);
+    }
+
+    #[test_log::test]
+    fn applies_dew_point_with_both_inputs() {
+        let mut data = vec![vec![SensorData::new("outdoor_temp", SensorValue::Temp(20.0))]];
+        apply(&mut data, &[Derivation::DewPoint]);
+        assert_eq!(data.len(), 1);
+
+        data.push(vec![SensorData::new("out_humidity", SensorValue::Humidity(50.0))]);
+        apply(&mut data, &[Derivation::DewPoint]);
+        let dew_point = data.iter().flatten().find(|sensor| sensor.name() == "dew_point").unwrap();
+        assert!(matches!(dew_point.value(), SensorValue::Temp(val) if (val - 9.3).abs() < 0.1));
+    }
+
+    #[test_log::test]
+    fn maps_degrees_to_compass_points() {
+        let points: Vec<&str> = [0.0, 11.0, 12.0, 45.0, 90.0, 135.0, 180.0, 202.5, 270.0, 349.0, 359.9, 360.0, -90.0]
+            .into_iter().map(compass_point).collect();
+        assert_eq!(points, ["N", "N", "NNE", "NE", "E", "SE", "S", "SSW", "W", "N", "N", "N", "W"]);
+
+        let mut data = vec![vec![SensorData::new("wind_dir", SensorValue::Direction(225))]];
+        apply(&mut data, &[Derivation::WindCardinal]);
+        assert!(matches!(data[1][..], [ref sensor] if sensor.name() == "wind_cardinal" && matches!(sensor.value(), SensorValue::Compass("SW"))));
+    }
+}
@@ -2,34 +2,91 @@
 // Protocol:
 //   https://osswww.ecowitt.net/uploads/20210716/WN1900%20GW1000,1100%20WH2680,2650%20telenet%20v1.6.0%20.pdf
 //
-use std::{collections::HashMap, time::Duration};
-use std::net::{TcpStream, SocketAddr, Ipv4Addr};
+use std::{collections::{HashMap, HashSet}, sync::{Mutex, OnceLock, atomic::{AtomicU32, Ordering}}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 use std::str::{self, FromStr};
-use std::io::{Read, Write, Error, ErrorKind};
+use std::fmt;
 use std::thread::sleep;
 
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use serde_json::{json, Value};
 
-const HEADER: &'static [u8] = &[ 0xFF, 0xFF];
+use crate::capture::FrameCapture;
+use crate::http::{HttpTransport, HTTP_PORT};
+use crate::transport::{Transport, TcpTransport, ResponseTooLarge};
+
+const HEADER: &[u8] = &[ 0xFF, 0xFF];
 
 #[derive(Debug)]
 pub struct SensorGateway {
-    firmware: Option<String>,
-    mac_address: Option<String>,
+    // Read on first use, a failed read is kept as None so the name stays stable
+    firmware: OnceLock<Option<String>>,
+    mac_address: OnceLock<Option<String>>,
+    system_info: OnceLock<Option<SystemInfo>>,
     
     max_tries: u32,
     retry_wait: Duration,
-    socket_timeout: Duration,
+    max_retry_wait: Duration,
+    min_command_interval: Duration,
+    // When the last command was sent, to keep commands min_command_interval apart
+    last_command: Mutex<Option<Instant>>,
+    // Polls failed in a row, to tell a gateway rebooting from one that is gone
+    failed_polls: AtomicU32,
+    // Sensor id command the firmware answered, detected on the first successful read
+    sensor_id_command: Mutex<Option<GatewayCommands>>,
 
     ip_address: SocketAddr,
+    transport: Box<dyn Transport>,
+    // Raw responses are written here when set
+    capture: Option<FrameCapture>,
 
     sensors: Sensors,
 }
 
+#[derive(Debug)]
+pub enum GatewayError {
+    // Connecting, sending or receiving failed, worth retrying later
+    Io(std::io::Error),
+    Checksum { expected: u8, got: u8 },
+    BadCommand { expected: u8, got: u8 },
+    // The response doesn't start with the 0xFF 0xFF header, carries the bytes found instead
+    BadHeader(Vec<u8>),
+    // The response was received but its content could not be parsed
+    Parse(String),
+    // The response is shorter than its header or the command requires
+    ShortPayload,
+    // A write command answered with a non zero result
+    Rejected(String),
+    Unsupported(String),
+}
+
+// Socket timeout and retry behaviour when talking to a gateway
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionOptions {
+    pub socket_timeout: Duration,
+    pub max_tries: u32,
+    // Wait before the first retry, doubled for each further retry up to max_retry_wait
+    pub retry_wait: Duration,
+    pub max_retry_wait: Duration,
+    // Minimum time between sending two commands, for gateways that drop connections when hit too fast
+    pub min_command_interval: Duration,
+    // Keep one connection open for the commands of a poll instead of one per command
+    pub reuse_connection: bool,
+    // Longer responses are cut off here and fail validation
+    pub max_response_bytes: usize,
+    pub protocol: Protocol,
+}
+
 #[derive(Debug)]
 pub struct Sensors {
     // Holds ids, battery status and signal level
     parsers: HashMap<u8, ParseInfo<'static>>,
+    // Unknown sensor types already warned about
+    unknown_types: Mutex<HashSet<u8>>,
+    // Addresses already warned about being reported by more than one sensor
+    duplicate_addresses: Mutex<HashSet<u32>>,
+    // Type ids without a parser but with a known size, emitted as raw values named by `raw_<type id>`
+    raw_fields: HashMap<u8, (String, usize)>,
 }
 
 #[derive(Debug, Clone)]
@@ -43,7 +100,17 @@ pub struct SensorMetadata {
     pub signal: u8,
 }
 
-#[derive(Debug, Clone, Copy)]
+// How a sensor encodes the battery byte of the sensor id response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatteryKind {
+    // 0 ok, 1 low
+    Binary,
+    // 0-5 bars, 6 on external power
+    Level,
+    Volt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SensorBatteryState {
     Ok,
     Low,
@@ -51,13 +118,93 @@ pub enum SensorBatteryState {
     Unknown,
 }
 
+// Soil moisture calibration for one WH51 channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoilCalibration {
+    pub channel: u8,
+    // Current moisture in % and the raw AD value it was computed from
+    pub humidity: u8,
+    pub ad: u16,
+    // Use min_ad/max_ad below instead of the sensor defaults
+    pub custom: bool,
+    pub min_ad: u8,
+    pub max_ad: u16,
+}
+
+// Live moisture of one WH51 channel as the raw AD value and the range the percentage is computed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoilRaw {
+    // 0 based, channel 0 reports as soil_moist_1
+    pub channel: u8,
+    pub ad: u16,
+    pub min_ad: u16,
+    pub max_ad: u16,
+    pub humidity: u8,
+}
+
+impl From<SoilCalibration> for SoilRaw {
+    fn from(calibration: SoilCalibration) -> Self {
+        SoilRaw {
+            channel: calibration.channel,
+            ad: calibration.ad,
+            min_ad: calibration.min_ad as u16,
+            max_ad: calibration.max_ad,
+            humidity: calibration.humidity,
+        }
+    }
+}
+
+// System parameters of the gateway. The API doesn't report wifi signal or uptime,
+// only the settings used to talk to the sensors. The number of registered sensors is
+// the size of GatewayReading::metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemInfo {
+    // Sensor radio frequency in MHz
+    pub frequency: u16,
+    // Outdoor sensor array the gateway expects, WH24 or WH65
+    pub sensor_type: String,
+    pub utc_time: u32,
+    pub timezone_index: u8,
+    pub dst: bool,
+}
+
+// Everything known about a gateway from a single poll
+#[derive(Debug, Clone)]
+pub struct GatewayReading {
+    pub time: SystemTime,
+    // Sensor ids, battery and signal by sensor type and address
+    pub metadata: HashMap<(u8, u32), SensorMetadata>,
+    // Live data, one entry per field type reported by the gateway
+    pub data: Vec<Vec<SensorData>>,
+    // The live data ended in the middle of a field, fields after it are missing
+    pub truncated: bool,
+}
+
+impl GatewayReading {
+    // All fields of the live data in the order the gateway reported them
+    pub fn sensors(&self) -> impl Iterator<Item = &SensorData> {
+        self.data.iter().flatten()
+    }
+}
+
+// Rain accumulators that can be reset, Total resets day, week, month and year
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RainResetKind {
+    Day,
+    Week,
+    Month,
+    Year,
+    Event,
+    Total,
+}
+
 #[derive(Debug, Clone)]
 pub struct SensorData {
     field: String,
     value: SensorValue,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub enum SensorValue {
     Empty,
     Temp(f64),
@@ -65,6 +212,7 @@ pub enum SensorValue {
     Pressure(f64),
     Speed(f64),
     Rain(f64),
+    RainRate(f64),
     RainLarge(f64),
     Distance(i8),
     Direction(i16),
@@ -74,36 +222,101 @@ pub enum SensorValue {
     DateTime([u8; 6]),
     Pm10(f64),
     Pm25(f64),
-    Co2(i16),
+    // ppm, unsigned per the protocol
+    Co2(u16),
     Light(f64),
     Uv(f64),
     UvIndex(f64),
     Leak(f64),
     Moist(f64),
+    LeafWetness(f64),
     Battery(SensorBatteryState),
+    // Compass point like "NNE"
+    Compass(&'static str),
+    // Field of a type id from config.raw_fields, published as hex
+    Raw(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnitSystem {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+// How the gateway is polled, the binary command port or the http json api
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    #[default]
+    Tcp,
+    Http,
 }
 
 #[derive(Debug)]
 struct ParseInfo<'a> {
-    parse_fn: fn(&[u8]) -> Result<Vec<SensorValue>, String>,
+    parse_fn: fn(&[u8]) -> Result<Vec<SensorValue>, GatewayError>,
     field_names: Vec<&'a str>,
     size: usize,
 }
 
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
-enum GatewayCommands {
+pub(crate) enum GatewayCommands {
     ReadStationMac = 0x26,
     LiveData = 0x27,
+    ReadSoilCalibration = 0x28,
+    WriteSoilCalibration = 0x29,
+    ReadSystemParams = 0x30,
+    ReadRainData = 0x34,
+    WriteRainData = 0x35,
+    ReadGain = 0x36,
+    WriteGain = 0x37,
+    ReadCalibration = 0x38,
+    WriteCalibration = 0x39,
+    // Older firmware only, six byte records without the signal
+    ReadSensorId = 0x3a,
     ReadSensorIdNew = 0x3c,
     ReadFirmwareVersion = 0x50,
 }
 
+impl fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GatewayError::Io(err) => write!(f, "I/O error - {}", err),
+            GatewayError::Checksum { expected, got } =>
+                write!(f, "Invalid checksum in API response. Expected 0x{:02X}, received 0x{:02X}", expected, got),
+            GatewayError::BadCommand { expected, got } =>
+                write!(f, "Invalid command code in API response. Expected 0x{:02X}, received 0x{:02X}", expected, got),
+            GatewayError::BadHeader(got) =>
+                write!(f, "Invalid header in API response. Expected FF FF, received {}", SensorGateway::bytes_to_hex(got, " ")),
+            GatewayError::Parse(msg) => write!(f, "Failed to parse response - {}", msg),
+            GatewayError::ShortPayload => write!(f, "Response too short for its payload"),
+            GatewayError::Rejected(msg) => write!(f, "Gateway rejected {}", msg),
+            GatewayError::Unsupported(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for GatewayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GatewayError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for GatewayError {
+    fn from(err: std::io::Error) -> Self {
+        GatewayError::Io(err)
+    }
+}
+
 impl SensorData {
     pub fn new(field: &str, value: SensorValue) -> Self {
         SensorData {
             field : field.to_string(),
-            value: value.clone(),
+            value,
         }
     }
 
@@ -112,54 +325,143 @@ impl SensorData {
     }
 
     pub fn name(&self) -> &str {
-        return self.field.as_str();
+        self.field.as_str()
     }
 }
 
 impl SensorGateway {
-    pub fn new(ip_address: String, port: u16) -> Self {
-        let mut gateway = SensorGateway {
-            ip_address: std::net::SocketAddr::V4(std::net::SocketAddrV4::new(Ipv4Addr::from_str(&ip_address).unwrap(), port)),
-            max_tries: 3,
-            retry_wait: Duration::from_secs(2),
-            socket_timeout: Duration::from_secs(2),
-            sensors: Sensors::new(),
-            firmware: Option::None,
-            mac_address: Option::None,
-        };
+    pub fn new(ip_address: String, port: u16) -> Result<Self, String> {
+        Self::with_options(ip_address, port, ConnectionOptions::default())
+    }
 
-        let _not_used = gateway.update_sensor_metadata();
-        if let Ok(firmware) = gateway.get_firmware_version() {
-            gateway.firmware = Some(firmware);
+    pub fn with_options(ip_address: String, port: u16, options: ConnectionOptions) -> Result<Self, String> {
+        Self::with_capture(ip_address, port, options, None)
+    }
+
+    // Like with_options, additionally writing every raw response to `capture`
+    pub fn with_capture(ip_address: String, port: u16, options: ConnectionOptions, capture: Option<FrameCapture>) -> Result<Self, String> {
+        options.validate()?;
+        let address = Self::resolve_address(&ip_address, port)?;
+        let transport = Self::build_transport(&ip_address, port, address, &options);
+        Ok(Self::connect(address, transport, options, capture))
+    }
+
+    fn build_transport(ip_address: &str, port: u16, address: SocketAddr, options: &ConnectionOptions) -> Box<dyn Transport> {
+        match options.protocol {
+            Protocol::Tcp => Box::new(TcpTransport::new(address, options.socket_timeout)
+                .reuse_connection(options.reuse_connection)
+                .max_response_bytes(options.max_response_bytes)
+                .resolve_host(ip_address, port)),
+            // The api is always on the web port, `port` is the command port
+            Protocol::Http => Box::new(HttpTransport::new(SocketAddr::new(address.ip(), HTTP_PORT), options.socket_timeout)),
         }
-        
-        if let Ok(mac_address) = gateway.get_station_mac() {
-            gateway.mac_address = Some(mac_address);
+    }
+
+    // IP address, hostname or `host:port`, `port` is used when the address has none
+    pub fn resolve_address(address: &str, port: u16) -> Result<SocketAddr, String> {
+        if let Ok(ip) = address.parse::<IpAddr>() {
+            return Ok(SocketAddr::new(ip, port));
         }
 
+        let resolved = if address.contains(':') {
+            address.to_socket_addrs()
+        } else {
+            (address, port).to_socket_addrs()
+        };
+        let mut addresses = resolved.map_err(|e| format!("Invalid gateway address {} - {}", address, e))?;
+        addresses.next().ok_or(format!("Gateway address {} did not resolve to any address", address))
+    }
+
+    // Like with_options without talking to the gateway, the firmware, MAC address and system info are
+    // read when first used
+    pub fn new_lazy(ip_address: String, port: u16, options: ConnectionOptions) -> Result<Self, String> {
+        options.validate()?;
+        let address = Self::resolve_address(&ip_address, port)?;
+        let transport = Self::build_transport(&ip_address, port, address, &options);
+        Ok(Self::build(address, transport, options, None))
+    }
+
+    // Gateway at `ip_address` reached through a custom transport
+    pub fn with_transport(ip_address: SocketAddr, transport: Box<dyn Transport>) -> Self {
+        Self::connect(ip_address, transport, ConnectionOptions::default(), None)
+    }
+
+    // Like with_transport, reading the gateway info when first used
+    pub fn with_transport_lazy(ip_address: SocketAddr, transport: Box<dyn Transport>) -> Self {
+        Self::build(ip_address, transport, ConnectionOptions::default(), None)
+    }
+
+    fn connect(ip_address: SocketAddr, transport: Box<dyn Transport>, options: ConnectionOptions, capture: Option<FrameCapture>) -> Self {
+        let gateway = Self::build(ip_address, transport, options, capture);
+
+        gateway.transport.begin_session();
+        let _not_used = gateway.update_sensor_metadata();
+        gateway.firmware();
+        gateway.system_info();
+        gateway.name();
+        gateway.transport.end_session();
+
         gateway
     }
 
+    fn build(ip_address: SocketAddr, transport: Box<dyn Transport>, options: ConnectionOptions, capture: Option<FrameCapture>) -> Self {
+        SensorGateway {
+            ip_address,
+            transport,
+            capture,
+            max_tries: options.max_tries,
+            retry_wait: options.retry_wait,
+            max_retry_wait: options.max_retry_wait,
+            min_command_interval: options.min_command_interval,
+            last_command: Mutex::new(None),
+            failed_polls: AtomicU32::new(0),
+            sensor_id_command: Mutex::new(None),
+            sensors: Sensors::new(),
+            firmware: OnceLock::new(),
+            mac_address: OnceLock::new(),
+            system_info: OnceLock::new(),
+        }
+    }
+
     pub fn name(&self) -> String {
-        let mut name = self.mac_address.clone().unwrap().replace(":", "").to_lowercase();
+        let mac_address = self.mac_address.get_or_init(|| match self.get_station_mac() {
+            Ok(mac_address) => Some(mac_address),
+            Err(_) => {
+                log::warn!("Failed to read MAC address from {:?}, using the address as gateway name", self.ip_address);
+                None
+            }
+        });
+
+        let mut name = match mac_address {
+            Some(mac) => mac.replace(":", "").to_lowercase(),
+            // Stable fallback derived from the configured address when the MAC is unavailable
+            None => format!("gw_{}", self.ip_address.ip().to_string().replace(['.', ':'], "_")),
+        };
         if cfg!(debug_assertions) {
             // Make it possible to run both release and debug at the same time
-            name = name + "_debug";
+            name += "_debug";
         }
 
-        return name;
+        name
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.ip_address
     }
 
     pub fn version(&self) -> String {
         // TODO: something smarter?
-        return self.firmware.clone().unwrap().replace(":", "");
+        self.firmware()
     }
 
     pub fn firmware(&self) -> String {
-        return self.firmware.clone().unwrap().replace(":", "");
+        match self.firmware.get_or_init(|| self.get_firmware_version().ok()) {
+            Some(firmware) => firmware.replace(":", ""),
+            None => "unknown".to_string(),
+        }
     }
 
-    fn generate_checksum(data: &[u8]) -> u8 {
+    pub(crate) fn generate_checksum(data: &[u8]) -> u8 {
         let mut checksum = 0u8;
         for &byte in data.iter() {
             checksum = checksum.wrapping_add(byte);
@@ -167,28 +469,54 @@ impl SensorGateway {
         checksum
     }
 
-    fn validate_response(response: &[u8], command: &u8) -> Result<(), String> {
-        if response.get(2) == Some(command) {
-            let checksum = Self::generate_checksum(&response[2..response.len() - 1]);
+    // `accepted` lists the response codes valid for the command, the first one is reported on mismatch
+    fn validate_response(response: &[u8], accepted: &[u8]) -> Result<(), GatewayError> {
+        // A desynced stream or another device answering doesn't start with the header
+        if response.len() < HEADER.len() {
+            return Err(GatewayError::ShortPayload);
+        }
+        if &response[0..HEADER.len()] != HEADER {
+            return Err(GatewayError::BadHeader(response[0..HEADER.len()].to_vec()));
+        }
+
+        if response.get(2).is_some_and(|code| accepted.contains(code)) {
+            // Same bytes build_cmd_packet sums: command, size and payload
+            let checksum = Self::generate_checksum(&response[HEADER.len()..response.len() - 1]);
             let resp_checksum = *response.last().unwrap_or(&0);
             
             if checksum == resp_checksum {
                 Ok(())
             } else {
-                Err(format!("Invalid checksum in API response. Expected '{}' (0x{:02X}), received '{}' (0x{:02X}).", 
-                            checksum, checksum, resp_checksum, resp_checksum))
+                Err(GatewayError::Checksum { expected: checksum, got: resp_checksum })
             }
         } else {
             let resp_int = response.get(2).cloned().unwrap_or(0);  // Assuming a default value of 0 if response is too short, you can adjust as needed
-            Err(format!("Invalid command code in API response. Expected '{}' (0x{:02X}), received '{}' (0x{:02X}).", 
-                        command, command, resp_int, resp_int))
+            Err(GatewayError::BadCommand { expected: accepted.first().copied().unwrap_or(0), got: resp_int })
         }
     }
 
-    fn bytes_to_hex(data: &[u8], separator: &str) -> String {
+    pub(crate) fn bytes_to_hex(data: &[u8], separator: &str) -> String {
         data.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(separator)
     }
 
+    // The live data and sensor id responses have a two byte size field, everything else one byte
+    pub(crate) fn has_wide_size(cmd: u8) -> bool {
+        cmd == GatewayCommands::LiveData as u8 || cmd == GatewayCommands::ReadSensorIdNew as u8
+    }
+
+    // Total length of a response frame, or None until enough of the header has been read.
+    // The size field counts everything after the header.
+    pub(crate) fn expected_frame_len(frame: &[u8]) -> Option<usize> {
+        let cmd = *frame.get(2)?;
+        let size = if Self::has_wide_size(cmd) {
+            u16::from_be_bytes([*frame.get(3)?, *frame.get(4)?]) as usize
+        } else {
+            *frame.get(3)? as usize
+        };
+
+        Some(HEADER.len() + size)
+    }
+
     fn build_cmd_packet(&self, cmd: &GatewayCommands, payload: &[u8]) -> Vec<u8> {
         let size = payload.len() as u8 + 3; // cmd+size+checksum
 
@@ -197,191 +525,662 @@ impl SensorGateway {
         body.push(size);
         body.extend_from_slice(payload);
 
+        // Everything between the header and the checksum, as checked by validate_response
         let checksum = SensorGateway::generate_checksum(&body);
 
         let mut packet = Vec::new();
-        packet.extend_from_slice(&HEADER);
+        packet.extend_from_slice(HEADER);
         packet.append(&mut body);
         packet.push(checksum);
 
         packet
     }
 
-    fn connect_and_send_packet(&self, packet: &[u8]) -> Result<Vec<u8>, std::io::Error> {
-        let mut s: TcpStream = TcpStream::connect_timeout(&self.ip_address, self.socket_timeout)?;
-
-        s.set_read_timeout(Some(self.socket_timeout))?;
-        s.set_write_timeout(Some(self.socket_timeout))?;
-
-        log::trace!("Sending packet {:?} to {:?}", packet, &self.ip_address);
-
-        // Send the packet.
-        s.write_all(packet)?;
-
-        let mut rx_bytes = [0u8; 1024];
-        let mut vec = Vec::new();
-        let result = s.read(&mut rx_bytes);
+    // Exponential backoff capped at `max`, `jitter` shortens the delay by up to a quarter so
+    // several gateways don't retry in lockstep
+    pub(crate) fn backoff_delay(base: Duration, max: Duration, attempt: u32, jitter: u32) -> Duration {
+        let delay = base.saturating_mul(2u32.saturating_pow(attempt)).min(max);
+        delay - delay * (jitter % 25) / 100
+    }
 
-        // TODO: check if we need to read more
-        match result {
-            Ok(n) => {
-                vec.extend_from_slice(&rx_bytes[0..n]);
-                log::trace!("Received packet {:?} of size {:?} from {:?}", vec, n, &self.ip_address);
-            },
-            Err(error) => {
-                log::error!("Failed to receive packet from {:?} - error {:?}, original packet {:?}", &self.ip_address, error, packet);
-            }
-        }
+    // Time to wait before sending a command at `now` so it is at least `min_interval` after the last one
+    pub(crate) fn command_delay(last: Option<Instant>, now: Instant, min_interval: Duration) -> Duration {
+        last.map_or(Duration::ZERO, |last| (last + min_interval).saturating_duration_since(now))
+    }
 
-        let res = s.shutdown(std::net::Shutdown::Both);
-        if res.is_err() {
-            log::error!("Failed to shutdown connection to {:?}", &self.ip_address);
+    fn wait_for_command_slot(&self) {
+        let mut last_command = self.last_command.lock().unwrap();
+        let delay = Self::command_delay(*last_command, Instant::now(), self.min_command_interval);
+        if !delay.is_zero() {
+            log::trace!("Waiting {:?} before the next command", delay);
+            sleep(delay);
         }
+        *last_command = Some(Instant::now());
+    }
 
-        Ok(vec)
+    fn send_cmd(&self, cmd: &GatewayCommands, payload: &[u8]) -> Result<Vec<u8>, GatewayError> {
+        self.send_cmd_expecting(cmd, payload, &[*cmd as u8])
     }
 
-    fn send_cmd(&self, cmd: &GatewayCommands, payload: &[u8]) -> Result<Vec<u8>, Error> {
-        let mut response: Vec<u8>;
+    // Like send_cmd for commands whose response code can differ from the request, e.g. on some firmware
+    fn send_cmd_expecting(&self, cmd: &GatewayCommands, payload: &[u8], accepted: &[u8]) -> Result<Vec<u8>, GatewayError> {
+        let _span = tracing::debug_span!("send_cmd", cmd = ?cmd).entered();
+        let mut last_error = None;
 
         for attempt in 0..self.max_tries {
             // Construct the message packet.
             let packet = self.build_cmd_packet(cmd, payload);
 
-            // Wrap in a `while` loop to handle retries.
-            match self.connect_and_send_packet(&packet) {
-                Ok(data) => response = data,
-                Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
+            self.wait_for_command_slot();
+            match self.transport.send_and_receive(&packet) {
+                // Check if the response is valid.
+                Ok(response) => {
+                    // Captured before validation, broken responses are the interesting ones
+                    if let Some(capture) = &self.capture {
+                        capture.record(*cmd as u8, &response);
+                    }
+
+                    match SensorGateway::validate_response(&response, accepted) {
+                        Ok(_) => return Ok(response),
+                        Err(e) => {
+                            // Some other error occurred in check_response(), perhaps the response was malformed.
+                            // Log the error and continue.
+                            log::warn!("Invalid response to command '{:?}' on attempt {}/{} from {:?}: {}", cmd, attempt + 1, self.max_tries, self.ip_address, e);
+                            last_error = Some(e);
+                        }
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
                     // A socket timeout occurred, log it.
-                    println!("Failed to obtain response to attempt {} to send command '{:?}': {}", attempt + 1, cmd, e);
-                    continue;
+                    log::warn!("Timed out waiting for response to command '{:?}' on attempt {}/{} to {:?}: {}", cmd, attempt + 1, self.max_tries, self.ip_address, e);
+                    last_error = Some(e.into());
                 }
-                Err(ref e) => {
-                    // An exception was encountered, log it.
-                    println!("Failed attempt {} to send command '{:?}': {}", attempt + 1, cmd, e);
-                    continue;
+                // The transport can't carry the command at all, e.g. over http
+                Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+                    return Err(GatewayError::Unsupported(e.to_string()));
                 }
-            }
-
-            // Check if the response is valid.
-            match SensorGateway::validate_response( &response, &(*cmd as u8)) {
-                Ok(_) => return Ok(response),
-                Err(ref e) => {
-                    // Some other error occurred in check_response(), perhaps the response was malformed.
-                    // Log the error and continue.
-                    println!("Unexpected exception occurred while checking response to attempt {} to send command '{:?}': {}", attempt + 1, cmd, e);
+                // Retrying gets the same response, only a larger max_response_bytes helps
+                Err(e) if e.get_ref().is_some_and(|source| source.is::<ResponseTooLarge>()) => {
+                    log::error!("Response to command '{:?}' from {:?} is too large: {}", cmd, self.ip_address, e);
+                    return Err(e.into());
+                }
+                Err(e) => {
+                    // An exception was encountered, log it.
+                    log::warn!("Failed to send command '{:?}' on attempt {}/{} to {:?}: {}", cmd, attempt + 1, self.max_tries, self.ip_address, e);
+                    last_error = Some(e.into());
                 }
             }
 
-            // Sleep before our next attempt, but skip the sleep if we have just made our last attempt.
+            // Back off before our next attempt, a rebooting gateway needs a while to come back,
+            // but skip the sleep if we have just made our last attempt.
             if attempt < self.max_tries - 1 {
-                log::trace!("Retry {:?} sleeping for {:?}", attempt, self.retry_wait);
-                sleep(self.retry_wait);
+                let jitter = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos();
+                let delay = Self::backoff_delay(self.retry_wait, self.max_retry_wait, attempt, jitter);
+                log::trace!("Retry {:?} sleeping for {:?}", attempt, delay);
+                sleep(delay);
             }
         }
 
-        return Err(Error::new(ErrorKind::Other, format!("Failed to obtain response to command '{:?}' after {} attempts", cmd, self.max_tries)));
+        log::error!("Giving up on command '{:?}' to {:?} after {} attempts", cmd, self.ip_address, self.max_tries);
+        // Report why the last attempt failed
+        Err(last_error.unwrap_or_else(|| std::io::Error::other(format!("No attempts made for command '{:?}'", cmd)).into()))
     }
 
-    fn parse_live_data(&self, response: &[u8]) -> Result<Vec<Vec<SensorData>>, String> {
-        // Obtain the payload size as a big-endian unsigned short
+    // Parsed fields and whether the data ended in the middle of a field
+    fn parse_live_data(&self, response: &[u8]) -> Result<(Vec<Vec<SensorData>>, bool), GatewayError> {
+        if response.len() < 5 {
+            log::debug!("Live data response too short for header - {:?}", response);
+            return Err(GatewayError::ShortPayload);
+        }
+
+        // Obtain the payload size as a big-endian unsigned short, it includes the command,
+        // the size itself and the checksum
         let payload_size = u16::from_be_bytes([response[3], response[4]]) as usize;
+        if payload_size < 4 {
+            return Err(GatewayError::Parse(format!("Invalid live data payload size {}", payload_size)));
+        }
 
         // Check if the response has enough data for the payload
-        if response.len() < payload_size {
-            return Err(format!("Payload size does not match response length len: {:?} payload:{:?}", response.len(), payload_size + 5));
+        let end = 5 + payload_size - 4;
+        if response.len() < end {
+            log::debug!("Payload size does not match response length len: {:?} expected:{:?}", response.len(), payload_size + 2);
+            return Err(GatewayError::ShortPayload);
         }
 
-        let val = self.sensors.parse_live_data(&response[5..5 + payload_size - 4]);
-        val
+        self.sensors.parse_live_data_partial(&response[5..end])
     }
 
-    pub fn update_sensor_metadata(&self) -> Result<HashMap<u32, SensorMetadata>, String> {
-        let sensor_ids = self.send_cmd(&GatewayCommands::ReadSensorIdNew, &[]);
-        match sensor_ids {
-            Ok(data) => {
-                self.sensors.update_metadata(&data)
-            },
+    pub fn update_sensor_metadata(&self) -> Result<HashMap<(u8, u32), SensorMetadata>, GatewayError> {
+        let command = *self.sensor_id_command.lock().unwrap();
+        let metadata = match command {
+            Some(GatewayCommands::ReadSensorId) => self.read_sensor_ids_after_legacy(),
+            Some(_) => self.read_sensor_ids(),
+            None => self.detect_sensor_id_command(),
+        };
+
+        if let Err(err) = &metadata {
+            log::error!("Failed to read sensor metadata - {}", err);
+        }
+        metadata
+    }
+
+    fn read_sensor_ids(&self) -> Result<HashMap<(u8, u32), SensorMetadata>, GatewayError> {
+        self.send_cmd(&GatewayCommands::ReadSensorIdNew, &[]).and_then(|data| self.sensors.update_metadata(&data))
+    }
+
+    fn read_sensor_ids_legacy(&self) -> Result<HashMap<(u8, u32), SensorMetadata>, GatewayError> {
+        self.send_cmd(&GatewayCommands::ReadSensorId, &[]).and_then(|data| self.sensors.update_metadata_legacy(&data))
+    }
+
+    // The gateway answered the current sensor id command, but not with a valid sensor id list.
+    // Failing to reach it says nothing about the commands it supports
+    fn is_unsupported_response(err: &GatewayError) -> bool {
+        match err {
+            GatewayError::Io(err) => err.kind() == std::io::ErrorKind::Unsupported,
+            _ => true,
+        }
+    }
+
+    // Try the current sensor id command, then the legacy one if the gateway doesn't support it.
+    // Only a successful read is cached
+    fn detect_sensor_id_command(&self) -> Result<HashMap<(u8, u32), SensorMetadata>, GatewayError> {
+        let err = match self.read_sensor_ids() {
+            Ok(metadata) => {
+                *self.sensor_id_command.lock().unwrap() = Some(GatewayCommands::ReadSensorIdNew);
+                return Ok(metadata);
+            }
+            Err(err) if Self::is_unsupported_response(&err) => err,
+            Err(err) => return Err(err),
+        };
+
+        log::info!("Reading sensor ids from {:?} failed - {}, trying the legacy command", self.ip_address, err);
+        match self.read_sensor_ids_legacy() {
+            Ok(metadata) => {
+                log::info!("Gateway {:?} only supports the legacy sensor id command, battery without signal", self.ip_address);
+                *self.sensor_id_command.lock().unwrap() = Some(GatewayCommands::ReadSensorId);
+                Ok(metadata)
+            }
+            Err(_) => Err(err),
+        }
+    }
+
+    // The current command is still tried first, a firmware update may have added it
+    fn read_sensor_ids_after_legacy(&self) -> Result<HashMap<(u8, u32), SensorMetadata>, GatewayError> {
+        match self.read_sensor_ids() {
+            Ok(metadata) => {
+                log::info!("Gateway {:?} now supports the current sensor id command", self.ip_address);
+                *self.sensor_id_command.lock().unwrap() = Some(GatewayCommands::ReadSensorIdNew);
+                Ok(metadata)
+            }
             Err(err) => {
-                log::error!("Failed to parse sensor metadata - {:?}", err);
-                Err(format!("Failed to parse sensor metadata - {:?}", err))
+                log::debug!("Reading sensor ids from {:?} failed - {}, using the legacy command", self.ip_address, err);
+                self.read_sensor_ids_legacy()
             }
         }
     }
 
-    pub fn get_live_data(&self) -> Result<Vec<Vec<SensorData>>, String> {
+    pub fn get_live_data(&self) -> Result<Vec<Vec<SensorData>>, GatewayError> {
+        self.read_live_data().map(|(data, _)| data)
+    }
+
+    // Live data without the grouping by field type, in the order the gateway reported it
+    pub fn get_live_data_flat(&self) -> Result<Vec<SensorData>, GatewayError> {
+        self.get_live_data().map(|data| data.into_iter().flatten().collect())
+    }
+
+    fn read_live_data(&self) -> Result<(Vec<Vec<SensorData>>, bool), GatewayError> {
         let live_data = self.send_cmd(&GatewayCommands::LiveData, &[]);
         match live_data {
             Ok(data) => {
                 self.parse_live_data(&data)
             }
             Err(err) => {
-                log::error!("Failed to parse sensor live data - {:?}", err);
-                Err(format!("Failed to parse sensor live data - {:?}", err))
+                log::error!("Failed to read sensor live data - {}", err);
+                Err(err)
             }
         }
     }
 
-    pub fn get_firmware_version(&mut self) -> Result<String, String> {
+    // Read metadata and live data in one call
+    pub fn poll(&self) -> Result<GatewayReading, GatewayError> {
+        self.transport.begin_session();
+        let metadata = self.update_sensor_metadata();
+        let data = self.read_live_data();
+        self.transport.end_session();
+
+        let reading = match (metadata, data) {
+            (Ok(metadata), Ok((data, truncated))) => Ok(GatewayReading {
+                time: SystemTime::now(),
+                metadata,
+                data,
+                truncated,
+            }),
+            (Err(err), _) | (_, Err(err)) => Err(err),
+        };
+
+        match reading {
+            Ok(_) => self.failed_polls.store(0, Ordering::Relaxed),
+            Err(_) => { self.failed_polls.fetch_add(1, Ordering::Relaxed); }
+        }
+        reading
+    }
+
+    pub fn known_fields(&self) -> Vec<(u8, &'static str, usize)> {
+        self.sensors.known_fields()
+    }
+
+    // Every field name the live data can have, including the raw fields
+    pub fn field_names(&self) -> Vec<String> {
+        self.sensors.field_names()
+    }
+
+    // Number of polls failed in a row, 0 after a successful poll
+    pub fn failed_polls(&self) -> u32 {
+        self.failed_polls.load(Ordering::Relaxed)
+    }
+
+    pub fn get_firmware_version(&self) -> Result<String, GatewayError> {
         let firmware_data = self.send_cmd(&GatewayCommands::ReadFirmwareVersion,&[]);
         match firmware_data {
             Ok(data) => {
-                let fw_size = data[4] as usize;
-                let fw_bytes = &data[5..5 + fw_size];
+                let fw_size = *data.get(4).ok_or(GatewayError::ShortPayload)? as usize;
+                let fw_bytes = data.get(5..5 + fw_size).ok_or(GatewayError::ShortPayload)?;
                 match String::from_utf8(fw_bytes.to_vec()) {
-                    Ok(s) => return Ok(s),
-                    Err(_) => return Err(format!("Invalid UTF-8 sequence {:?}", fw_bytes)),
-                };
+                    Ok(s) => Ok(s),
+                    Err(_) => Err(GatewayError::Parse(format!("Invalid UTF-8 sequence {:?}", fw_bytes))),
+                }
             }
             Err(err) => {
-                log::error!("Failed to parse firmware version - {:?}", err);
-                Err(format!("Failed to parse firmware version - {:?}", err))
+                log::error!("Failed to read firmware version - {}", err);
+                Err(err)
             }
         }
     }
 
-    pub fn get_station_mac(&mut self) -> Result<String, String> {
+    // System info read when connecting, or on first use for a lazy gateway
+    pub fn system_info(&self) -> Option<&SystemInfo> {
+        self.system_info.get_or_init(|| match self.get_system_info() {
+            Ok(system_info) => Some(system_info),
+            Err(err) => {
+                log::warn!("Failed to read system info from {:?} - {}", self.ip_address, err);
+                None
+            }
+        }).as_ref()
+    }
+
+    // Live data fields of `type_id` are emitted as `raw_<type id>` instead of ending the parse
+    pub fn add_raw_field(&mut self, type_id: u8, size: usize) -> Result<(), String> {
+        self.sensors.add_raw_field(type_id, size)
+    }
+
+    pub fn get_system_info(&self) -> Result<SystemInfo, GatewayError> {
+        let data = self.send_cmd(&GatewayCommands::ReadSystemParams, &[])?;
+        Self::parse_system_info(&data)
+    }
+
+    pub(crate) fn parse_system_info(data: &[u8]) -> Result<SystemInfo, GatewayError> {
+        // Frequency, sensor type, utc time, time zone index and dst flags
+        let params = data.get(4..12).ok_or(GatewayError::ShortPayload)?;
+        let frequency = match params[0] {
+            0 => 433,
+            1 => 868,
+            2 => 915,
+            3 => 920,
+            other => return Err(GatewayError::Parse(format!("Unknown frequency {}", other))),
+        };
+        let sensor_type = match params[1] {
+            0 => "WH24",
+            _ => "WH65",
+        };
+
+        Ok(SystemInfo {
+            frequency,
+            sensor_type: sensor_type.to_string(),
+            utc_time: u32::from_be_bytes(params[2..6].try_into().unwrap()),
+            timezone_index: params[6],
+            dst: params[7] & 0x01 != 0,
+        })
+    }
+
+    pub fn get_soil_calibration(&self) -> Result<Vec<SoilCalibration>, GatewayError> {
+        let data = match self.send_cmd(&GatewayCommands::ReadSoilCalibration, &[]) {
+            Ok(data) => data,
+            Err(err) => {
+                log::error!("Failed to read soil calibration - {}", err);
+                return Err(err);
+            }
+        };
+
+        if data.len() < 5 {
+            return Err(GatewayError::ShortPayload);
+        }
+
+        // Each channel is 8 bytes: channel, humidity, ad(2), custom, min ad, max ad(2)
+        let records = data[4..data.len() - 1].chunks_exact(8);
+        if !records.remainder().is_empty() {
+            return Err(GatewayError::Parse(format!("Invalid soil calibration payload length {:?}", data.len())));
+        }
+
+        Ok(records.map(|record| SoilCalibration {
+            channel: record[0],
+            humidity: record[1],
+            ad: u16::from_be_bytes([record[2], record[3]]),
+            custom: record[4] != 0,
+            min_ad: record[5],
+            max_ad: u16::from_be_bytes([record[6], record[7]]),
+        }).collect())
+    }
+
+    // The gateway reports the live AD value with the calibration, for calibrating probes
+    pub fn get_soil_raw(&self) -> Result<Vec<SoilRaw>, GatewayError> {
+        Ok(self.get_soil_calibration()?.into_iter().map(SoilRaw::from).collect())
+    }
+
+    pub fn set_soil_calibration(&self, calibration: &SoilCalibration) -> Result<(), GatewayError> {
+        let max_ad = calibration.max_ad.to_be_bytes();
+        let payload = [calibration.channel, calibration.custom as u8, calibration.min_ad, max_ad[0], max_ad[1]];
+
+        match self.send_cmd(&GatewayCommands::WriteSoilCalibration, &payload) {
+            // Write commands answer with a single result byte, 0 is success
+            Ok(data) if data.get(4) == Some(&0) => Ok(()),
+            Ok(data) => Err(GatewayError::Rejected(format!("soil calibration for channel {} - response {:?}", calibration.channel, data))),
+            Err(err) => {
+                log::error!("Failed to write soil calibration - {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    // Zero the selected rain accumulators and keep the others
+    pub fn reset_rain(&self, which: RainResetKind) -> Result<(), GatewayError> {
+        let data = self.send_cmd(&GatewayCommands::ReadRainData, &[])?;
+
+        // rate, day, week, month and year as 4 byte values
+        if data.len() < 25 {
+            log::debug!("Rain data response too short - {:?}", data);
+            return Err(GatewayError::ShortPayload);
+        }
+        let mut rain = [0u32; 4];
+        for (i, value) in rain.iter_mut().enumerate() {
+            let offset = 8 + i * 4;
+            *value = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        }
+
+        let payload = Self::rain_reset_payload(rain, which)?;
+        match self.send_cmd(&GatewayCommands::WriteRainData, &payload) {
+            Ok(data) if data.get(4) == Some(&0) => Ok(()),
+            Ok(data) => Err(GatewayError::Rejected(format!("rain reset {:?} - response {:?}", which, data))),
+            Err(err) => {
+                log::error!("Failed to write rain data - {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    // Multiplier applied to the rain gauge readings
+    pub fn get_rain_gain(&self) -> Result<f64, GatewayError> {
+        let gains = self.read_gains()?;
+        Ok(u16::from_be_bytes([gains[8], gains[9]]) as f64 / 100.0)
+    }
+
+    pub fn set_rain_gain(&self, gain: f64) -> Result<(), GatewayError> {
+        // The other gains are written back unchanged
+        let payload = Self::rain_gain_payload(self.read_gains()?, gain)?;
+        match self.send_cmd(&GatewayCommands::WriteGain, &payload) {
+            Ok(data) if data.get(4) == Some(&0) => Ok(()),
+            Ok(data) => Err(GatewayError::Rejected(format!("rain gain {} - response {:?}", gain, data))),
+            Err(err) => {
+                log::error!("Failed to write gains - {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    // Gain block: fixed(2), uv(2), solar(2), wind(2), rain(2) and reserved bytes
+    fn read_gains(&self) -> Result<Vec<u8>, GatewayError> {
+        let data = self.send_cmd(&GatewayCommands::ReadGain, &[])?;
+        let gains = data.get(4..data.len() - 1).filter(|gains| gains.len() >= 10).ok_or(GatewayError::ShortPayload)?;
+        Ok(gains.to_vec())
+    }
+
+    // Payload for WriteGain from the current gain block with the rain gain replaced
+    pub(crate) fn rain_gain_payload(mut gains: Vec<u8>, gain: f64) -> Result<Vec<u8>, GatewayError> {
+        if !(0.1..=5.0).contains(&gain) {
+            return Err(GatewayError::Unsupported(format!("Rain gain {} is outside 0.10 to 5.00", gain)));
+        }
+        gains[8..10].copy_from_slice(&((gain * 100.0).round() as u16).to_be_bytes());
+        Ok(gains)
+    }
+
+    // Offset in hPa the gateway adds to the absolute pressure to report the relative pressure
+    pub fn get_relative_pressure_offset(&self) -> Result<f64, GatewayError> {
+        let calibration = self.read_calibration()?;
+        Ok(i32::from_be_bytes(calibration[7..11].try_into().unwrap()) as f64 / 10.0)
+    }
+
+    pub fn set_relative_pressure_offset(&self, offset: f64) -> Result<(), GatewayError> {
+        // The other offsets are written back unchanged
+        let payload = Self::relative_pressure_offset_payload(self.read_calibration()?, offset)?;
+        match self.send_cmd(&GatewayCommands::WriteCalibration, &payload) {
+            Ok(data) if data.get(4) == Some(&0) => Ok(()),
+            Ok(data) => Err(GatewayError::Rejected(format!("relative pressure offset {} - response {:?}", offset, data))),
+            Err(err) => {
+                log::error!("Failed to write calibration - {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    // Calibration block: indoor temp(2), indoor humidity, abs pressure(4), rel pressure(4),
+    // outdoor temp(2), outdoor humidity and wind direction(2) offsets
+    fn read_calibration(&self) -> Result<Vec<u8>, GatewayError> {
+        let data = self.send_cmd(&GatewayCommands::ReadCalibration, &[])?;
+        let calibration = data.get(4..data.len() - 1).filter(|calibration| calibration.len() >= 16).ok_or(GatewayError::ShortPayload)?;
+        Ok(calibration[..16].to_vec())
+    }
+
+    // Payload for WriteCalibration from the current calibration block with the relative pressure offset replaced
+    pub(crate) fn relative_pressure_offset_payload(mut calibration: Vec<u8>, offset: f64) -> Result<Vec<u8>, GatewayError> {
+        if !(-1000.0..=1000.0).contains(&offset) {
+            return Err(GatewayError::Unsupported(format!("Relative pressure offset {} is outside -1000 to 1000 hPa", offset)));
+        }
+        calibration[7..11].copy_from_slice(&((offset * 10.0).round() as i32).to_be_bytes());
+        Ok(calibration)
+    }
+
+    // Payload for WriteRainData from the current day, week, month and year values
+    pub(crate) fn rain_reset_payload(mut rain: [u32; 4], which: RainResetKind) -> Result<Vec<u8>, GatewayError> {
+        match which {
+            RainResetKind::Day => rain[0] = 0,
+            RainResetKind::Week => rain[1] = 0,
+            RainResetKind::Month => rain[2] = 0,
+            RainResetKind::Year => rain[3] = 0,
+            RainResetKind::Total => rain = [0; 4],
+            RainResetKind::Event => return Err(GatewayError::Unsupported("The rain event counter can't be written with WriteRainData".to_string())),
+        }
+
+        Ok(rain.iter().flat_map(|value| value.to_be_bytes()).collect())
+    }
+
+    pub fn get_station_mac(&self) -> Result<String, GatewayError> {
         let mac = self.send_cmd(&GatewayCommands::ReadStationMac,&[]);
         match mac {
             Ok(data) => {
-                Ok(SensorGateway::bytes_to_hex(&data[3..10], ":"))
+                let mac = data.get(3..10).ok_or(GatewayError::ShortPayload)?;
+                Ok(SensorGateway::bytes_to_hex(mac, ":"))
             }
             Err(err) => {
-                log::error!("Failed to parse firmware version - {:?}", err);
-                Err(format!("Failed to parse firmware version - {:?}", err))
+                log::error!("Failed to read station mac - {}", err);
+                Err(err)
             }
         }
     }
 }
 
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        ConnectionOptions {
+            socket_timeout: Duration::from_secs(2),
+            max_tries: 3,
+            retry_wait: Duration::from_secs(2),
+            max_retry_wait: Duration::from_secs(10),
+            min_command_interval: Duration::ZERO,
+            reuse_connection: false,
+            max_response_bytes: 4096,
+            protocol: Protocol::Tcp,
+        }
+    }
+}
+
+impl ConnectionOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_tries == 0 {
+            return Err("max_tries must be at least 1".to_string());
+        }
+        if self.socket_timeout.is_zero() {
+            return Err("socket_timeout must be greater than zero".to_string());
+        }
+        if self.max_response_bytes < 64 {
+            return Err("max_response_bytes must be at least 64".to_string());
+        }
+        Ok(())
+    }
+
+    // Longest a single command can take with all retries, a connect and a read timeout per try
+    pub fn worst_case_duration(&self) -> Duration {
+        let waits: Duration = (0..self.max_tries.saturating_sub(1))
+            .map(|attempt| SensorGateway::backoff_delay(self.retry_wait, self.max_retry_wait, attempt, 0))
+            .sum();
+        self.socket_timeout.saturating_mul(2 * self.max_tries) + waits
+    }
+}
+
+// Time zone used when formatting timestamps reported by the gateway
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayTimeZone {
+    #[default]
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl DisplayTimeZone {
+    // ISO-8601 / RFC 3339 string in this time zone
+    pub fn format(&self, time: impl Into<DateTime<Utc>>) -> String {
+        let utc = time.into();
+        match self {
+            DisplayTimeZone::Utc => utc.to_rfc3339(),
+            DisplayTimeZone::Local => utc.with_timezone(&Local).to_rfc3339(),
+            DisplayTimeZone::Fixed(offset) => utc.with_timezone(offset).to_rfc3339(),
+        }
+    }
+
+    // Wall clock time in this time zone, None if it doesn't exist or is ambiguous
+    pub fn from_local(&self, naive: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        match self {
+            DisplayTimeZone::Utc => Some(naive.and_utc().fixed_offset()),
+            DisplayTimeZone::Local => Local.from_local_datetime(&naive).single().map(|time| time.fixed_offset()),
+            DisplayTimeZone::Fixed(offset) => offset.from_local_datetime(&naive).single(),
+        }
+    }
+}
+
+impl FromStr for DisplayTimeZone {
+    type Err = String;
+
+    // "utc", "local" or a fixed offset like "+02:00"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "utc" => Ok(DisplayTimeZone::Utc),
+            "local" => Ok(DisplayTimeZone::Local),
+            _ => s.parse::<FixedOffset>()
+                .map(DisplayTimeZone::Fixed)
+                .map_err(|_| format!("Unknown timezone {}, expected utc, local or an offset like +02:00", s)),
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tcp" => Ok(Protocol::Tcp),
+            "http" => Ok(Protocol::Http),
+            _ => Err(format!("Unknown protocol {:?}, expected tcp or http", s)),
+        }
+    }
+}
+
+impl FromStr for UnitSystem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "metric" => Ok(UnitSystem::Metric),
+            "imperial" => Ok(UnitSystem::Imperial),
+            _ => Err(format!("Unknown unit system {:?}, expected metric or imperial", s)),
+        }
+    }
+}
+
+impl UnitSystem {
+    // Map a metric unit (as reported by the gateway) to the matching unit in this system
+    pub fn unit_of_measurement(&self, unit: &str) -> String {
+        match self {
+            UnitSystem::Metric => unit.to_string(),
+            UnitSystem::Imperial => match unit {
+                "°C" => "°F",
+                "hPa" => "inHg",
+                "m/s" => "mph",
+                "mm" => "in",
+                "mm/h" => "in/h",
+                "km" => "mi",
+                _ => unit,
+            }.to_string(),
+        }
+    }
+}
+
 impl SensorValue {
-    fn round(x: &f64) -> f64 {
-        (x * 100.0).round() / 100.0
+    // Decimal places used unless a precision is given
+    pub const DEFAULT_PRECISION: u8 = 2;
+
+    fn round(x: &f64, precision: u8) -> f64 {
+        // Beyond 15 digits an f64 has nothing left to round
+        let factor = 10f64.powi(precision.min(15) as i32);
+        (x * factor).round() / factor
     }
 
     pub fn to_json_val(t: &SensorValue) -> Value {
-        log::trace!("to_json_val: {:?}", t);
-        match t {
+        t.to_json_val_with_precision(Self::DEFAULT_PRECISION)
+    }
+
+    // Like to_json_val with floating point values rounded to `precision` decimal places
+    pub fn to_json_val_with_precision(&self, precision: u8) -> Value {
+        log::trace!("to_json_val: {:?}", self);
+        match self {
             SensorValue::Empty => json!(null),
-            SensorValue::Temp(val) => json!(Self::round(val)),
-            SensorValue::Humidity(val) => json!(Self::round(val)),
-            SensorValue::Pressure(val) => json!(Self::round(val)),
-            SensorValue::Speed(val) => json!(Self::round(val)),
-            SensorValue::Rain(val) => json!(Self::round(val)),
-            SensorValue::RainLarge(val) => json!(Self::round(val)),
+            SensorValue::Temp(val) => json!(Self::round(val, precision)),
+            SensorValue::Humidity(val) => json!(Self::round(val, precision)),
+            SensorValue::Pressure(val) => json!(Self::round(val, precision)),
+            SensorValue::Speed(val) => json!(Self::round(val, precision)),
+            SensorValue::Rain(val) => json!(Self::round(val, precision)),
+            SensorValue::RainRate(val) => json!(Self::round(val, precision)),
+            SensorValue::RainLarge(val) => json!(Self::round(val, precision)),
             SensorValue::Distance(val) => json!(val),
             SensorValue::Direction(val) => json!(val),
             SensorValue::UtcTime(val) => json!(val),
             SensorValue::Count(val) => json!(val),
-            SensorValue::Gain(val) => json!(Self::round(val)),
+            SensorValue::Gain(val) => json!(Self::round(val, precision)),
             SensorValue::DateTime(val) => json!(format!("dt:{:02x} {:02x} {:02x} {:02x} {:02x} {:02x}",val[0],val[1],val[2],val[3],val[4],val[5])),
-            SensorValue::Pm10(val) => json!(Self::round(val)),
-            SensorValue::Pm25(val) => json!(Self::round(val)),
+            SensorValue::Pm10(val) => json!(Self::round(val, precision)),
+            SensorValue::Pm25(val) => json!(Self::round(val, precision)),
             SensorValue::Co2(val) => json!(val),
-            SensorValue::Light(val) => json!(Self::round(val)),
-            SensorValue::Uv(val) => json!(Self::round(val)),
-            SensorValue::UvIndex(val) => json!(Self::round(val)),
-            SensorValue::Leak(val) => json!(Self::round(val)),
+            SensorValue::Light(val) => json!(Self::round(val, precision)),
+            SensorValue::Uv(val) => json!(Self::round(val, precision)),
+            SensorValue::UvIndex(val) => json!(Self::round(val, precision)),
+            SensorValue::Leak(val) => json!(Self::round(val, precision)),
             SensorValue::Battery(val) => {
                 let str = match val {
                     SensorBatteryState::Ok => "ok",
@@ -391,128 +1190,257 @@ impl SensorValue {
                 };
                 json!(str)
             },
-            SensorValue::Moist(val) => json!(Self::round(val)),
+            SensorValue::Moist(val) => json!(Self::round(val, precision)),
+            SensorValue::LeafWetness(val) => json!(Self::round(val, precision)),
+            SensorValue::Compass(val) => json!(val),
+            SensorValue::Raw(val) => json!(SensorGateway::bytes_to_hex(val, "")),
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        !matches!(self, SensorValue::Empty | SensorValue::DateTime(_) | SensorValue::UtcTime(_) | SensorValue::Battery(_) | SensorValue::Compass(_) | SensorValue::Raw(_))
+    }
+
+    // Numeric value in metric units, rounded like the published json. None for timestamps, battery, compass, raw and empty values
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            SensorValue::Temp(val) | SensorValue::Humidity(val) | SensorValue::Pressure(val) | SensorValue::Speed(val)
+            | SensorValue::Rain(val) | SensorValue::RainRate(val) | SensorValue::RainLarge(val) | SensorValue::Gain(val)
+            | SensorValue::Pm10(val) | SensorValue::Pm25(val) | SensorValue::Light(val) | SensorValue::Uv(val)
+            | SensorValue::UvIndex(val) | SensorValue::Leak(val) | SensorValue::Moist(val) | SensorValue::LeafWetness(val) => Some(Self::round(&val, Self::DEFAULT_PRECISION)),
+            SensorValue::Distance(val) => Some(val as f64),
+            SensorValue::Direction(val) => Some(val as f64),
+            SensorValue::Count(val) => Some(val as f64),
+            SensorValue::Co2(val) => Some(val as f64),
+            SensorValue::Empty | SensorValue::UtcTime(_) | SensorValue::DateTime(_) | SensorValue::Battery(_) | SensorValue::Compass(_) | SensorValue::Raw(_) => None,
+        }
+    }
+
+    pub fn as_battery(&self) -> Option<SensorBatteryState> {
+        match self {
+            SensorValue::Battery(state) => Some(*state),
+            _ => None,
+        }
+    }
+
+    // Stable name of the variant, for backends that dispatch on the kind of value
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SensorValue::Empty => "empty",
+            SensorValue::Temp(_) => "temp",
+            SensorValue::Humidity(_) => "humidity",
+            SensorValue::Pressure(_) => "pressure",
+            SensorValue::Speed(_) => "speed",
+            SensorValue::Rain(_) => "rain",
+            SensorValue::RainRate(_) => "rain_rate",
+            SensorValue::RainLarge(_) => "rain_large",
+            SensorValue::Distance(_) => "distance",
+            SensorValue::Direction(_) => "direction",
+            SensorValue::UtcTime(_) => "utc_time",
+            SensorValue::Count(_) => "count",
+            SensorValue::Gain(_) => "gain",
+            SensorValue::DateTime(_) => "datetime",
+            SensorValue::Pm10(_) => "pm10",
+            SensorValue::Pm25(_) => "pm25",
+            SensorValue::Co2(_) => "co2",
+            SensorValue::Light(_) => "light",
+            SensorValue::Uv(_) => "uv",
+            SensorValue::UvIndex(_) => "uv_index",
+            SensorValue::Leak(_) => "leak",
+            SensorValue::Moist(_) => "moist",
+            SensorValue::LeafWetness(_) => "leaf_wetness",
+            SensorValue::Battery(_) => "battery",
+            SensorValue::Compass(_) => "compass",
+            SensorValue::Raw(_) => "raw",
+        }
+    }
+
+    // ISO-8601 timestamp for UtcTime, None for other values or when the gateway has no time yet
+    pub fn to_iso8601(&self, timezone: DisplayTimeZone) -> Option<String> {
+        match self {
+            SensorValue::UtcTime(val) if *val >= 0 => Some(timezone.format(DateTime::from_timestamp(*val as i64, 0)?)),
+            SensorValue::DateTime(_) => Some(self.to_datetime(timezone)?.to_rfc3339()),
+            _ => None,
         }
     }
 
-    pub fn parse_temp(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 2 { return Err("Invalid data length temp".to_string()); }
+    // Gateway clock from 0x18: year - 2000, month, day, hour, minute and second in the
+    // gateway's local time, interpreted in `timezone`
+    pub fn to_datetime(&self, timezone: DisplayTimeZone) -> Option<DateTime<FixedOffset>> {
+        let SensorValue::DateTime([year, month, day, hour, minute, second]) = *self else {
+            return None;
+        };
+
+        let naive = NaiveDate::from_ymd_opt(2000 + year as i32, month as u32, day as u32)?
+            .and_hms_opt(hour as u32, minute as u32, second as u32)?;
+        timezone.from_local(naive)
+    }
+
+    pub fn to_json_val_with_units(&self, units: UnitSystem) -> Value {
+        self.to_json_val_with_units_precision(units, Self::DEFAULT_PRECISION)
+    }
+
+    pub fn to_json_val_with_units_precision(&self, units: UnitSystem, precision: u8) -> Value {
+        if units == UnitSystem::Metric {
+            return self.to_json_val_with_precision(precision);
+        }
+
+        match self {
+            SensorValue::Temp(val) => json!(Self::round(&(val * 9.0 / 5.0 + 32.0), precision)),
+            SensorValue::Pressure(val) => json!(Self::round(&(val * 0.029_529_983), precision)),
+            SensorValue::Speed(val) => json!(Self::round(&(val * 2.236_936_292), precision)),
+            SensorValue::Rain(val) => json!(Self::round(&(val / 25.4), precision)),
+            SensorValue::RainRate(val) => json!(Self::round(&(val / 25.4), precision)),
+            SensorValue::RainLarge(val) => json!(Self::round(&(val / 25.4), precision)),
+            SensorValue::Distance(val) => json!(Self::round(&(*val as f64 * 0.621_371_192), precision)),
+            _ => self.to_json_val_with_precision(precision),
+        }
+    }
+
+    pub fn parse_temp(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 2 { return Err(GatewayError::Parse("Invalid data length temp".to_string())); }
         Ok(vec![SensorValue::Temp(i16::from_be_bytes(data[data.len() - 2..].try_into().unwrap()) as f64 / 10.0)])
     }
 
-    pub fn parse_humidity(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 1 { return Err("Invalid data length for humidity".to_string()); }
+    pub fn parse_humidity(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 1 { return Err(GatewayError::Parse("Invalid data length for humidity".to_string())); }
         Ok(vec![SensorValue::Humidity(data[0] as f64)])
     }
 
-    pub fn parse_moist(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 1 { return Err("Invalid data length for moist".to_string()); }
+    pub fn parse_moist(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 1 { return Err(GatewayError::Parse("Invalid data length for moist".to_string())); }
         Ok(vec![SensorValue::Moist(data[0] as f64)])
     }
 
-    pub fn parse_pressure(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() < 2 { return Err("Invalid data length for pressure".to_string()); }
+    pub fn parse_leaf_wetness(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 1 { return Err(GatewayError::Parse("Invalid data length for leaf wetness".to_string())); }
+        Ok(vec![SensorValue::LeafWetness(data[0] as f64)])
+    }
+
+    pub fn parse_pressure(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() < 2 { return Err(GatewayError::Parse("Invalid data length for pressure".to_string())); }
         Ok(vec![SensorValue::Pressure(i16::from_be_bytes(data[data.len() - 2..].try_into().unwrap()) as f64 / 10.0)])
     }
     
-    pub fn parse_speed(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() < 2 { return Err("Invalid data length for speed".to_string()); }
+    pub fn parse_speed(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() < 2 { return Err(GatewayError::Parse("Invalid data length for speed".to_string())); }
         Ok(vec![SensorValue::Speed(i16::from_be_bytes(data[data.len() - 2..].try_into().unwrap()) as f64 / 10.0)])
     }
 
-    pub fn parse_rain(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() < 2 { return Err("Invalid data length for rain".to_string()); }
+    pub fn parse_rain(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() < 2 { return Err(GatewayError::Parse("Invalid data length for rain".to_string())); }
         Ok(vec![SensorValue::Rain(i16::from_be_bytes(data[data.len() - 2..].try_into().unwrap()) as f64 / 10.0)])
     }
 
-    pub fn parse_rainlarge(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 4 { return Err("Invalid data length for rainlarge".to_string()); }
+    // Rain rate in mm/h, same scaling as the rain totals but a rate rather than an accumulation
+    pub fn parse_rain_rate(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() < 2 { return Err(GatewayError::Parse("Invalid data length for rain rate".to_string())); }
+        Ok(vec![SensorValue::RainRate(i16::from_be_bytes(data[data.len() - 2..].try_into().unwrap()) as f64 / 10.0)])
+    }
+
+    pub fn parse_rainlarge(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 4 { return Err(GatewayError::Parse("Invalid data length for rainlarge".to_string())); }
         Ok(vec![SensorValue::RainLarge(u32::from_be_bytes(data.try_into().unwrap()) as f64 / 10.0)])
     }
 
-    pub fn parse_distance(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 1 { return Err("Invalid data length for humidity".to_string()); }
+    pub fn parse_distance(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 1 { return Err(GatewayError::Parse("Invalid data length for humidity".to_string())); }
         Ok(vec![SensorValue::Distance(data[0] as i8)])
     }
 
-    pub fn parse_direction(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 2 { return Err("Invalid data lenght for direction".to_string()); }
+    pub fn parse_direction(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 2 { return Err(GatewayError::Parse("Invalid data lenght for direction".to_string())); }
         Ok(vec![SensorValue::Direction(i16::from_be_bytes(data[data.len() - 2..].try_into().unwrap()))])
     }
 
-    pub fn parse_count(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 4 { return Err("Invalid data length for count".to_string()); }
+    pub fn parse_count(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 4 { return Err(GatewayError::Parse("Invalid data length for count".to_string())); }
         Ok(vec![SensorValue::Count(u32::from_be_bytes(data.try_into().unwrap()))])
     }
 
-    pub fn parse_gain(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 4 { return Err("Invalid data length for gain".to_string()); }
-        Ok(vec![SensorValue::Gain(u32::from_be_bytes(data.try_into().unwrap()) as f64 / 100.0)])
+    // Multiplier in hundredths, 100 is a gain of 1.00
+    pub fn parse_gain(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 2 { return Err(GatewayError::Parse("Invalid data length for gain".to_string())); }
+        Ok(vec![SensorValue::Gain(u16::from_be_bytes(data.try_into().unwrap()) as f64 / 100.0)])
     }
 
-    pub fn parse_light(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 4 { return Err("Invalid data length for light".to_string()); }
+    pub fn parse_light(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 4 { return Err(GatewayError::Parse("Invalid data length for light".to_string())); }
         Ok(vec![SensorValue::Light(u32::from_be_bytes(data.try_into().unwrap()) as f64 / 100.0)])
     }
 
-    pub fn parse_uv(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() < 2 { return Err("Invalid data length for uv".to_string()); }
+    pub fn parse_uv(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() < 2 { return Err(GatewayError::Parse("Invalid data length for uv".to_string())); }
         Ok(vec![SensorValue::Uv(i16::from_be_bytes(data[data.len() - 2..].try_into().unwrap()) as f64 / 10.0)])
     }
 
-    pub fn parse_uv_index(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 1 { return Err("Invalid data length for uv index".to_string()); }
+    pub fn parse_uv_index(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 1 { return Err(GatewayError::Parse("Invalid data length for uv index".to_string())); }
         Ok(vec![SensorValue::UvIndex(data[0] as f64)])
     }
 
-    pub fn parse_pm10(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() < 2 { return Err("Invalid data length for pm10".to_string()); }
+    pub fn parse_pm10(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() < 2 { return Err(GatewayError::Parse("Invalid data length for pm10".to_string())); }
         Ok(vec![SensorValue::Pm10(i16::from_be_bytes(data[data.len() - 2..].try_into().unwrap()) as f64 / 10.0)])
     }
 
-    pub fn parse_pm25(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() < 2 { return Err("Invalid data length for pm25".to_string()); }
+    pub fn parse_pm25(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() < 2 { return Err(GatewayError::Parse("Invalid data length for pm25".to_string())); }
         Ok(vec![SensorValue::Pm25(i16::from_be_bytes(data[data.len() - 2..].try_into().unwrap()) as f64 / 10.0)])
     }
 
-    pub fn parse_leak(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 1 { return Err("Invalid data length for leak".to_string()); }
+    pub fn parse_leak(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 1 { return Err(GatewayError::Parse("Invalid data length for leak".to_string())); }
         Ok(vec![SensorValue::Leak(data[0] as f64)])
     }
 
-    pub fn parse_co2(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 2 { return Err("Invalid data lenght for co2".to_string()); }
-        Ok(vec![SensorValue::Co2(i16::from_be_bytes(data[data.len() - 2..].try_into().unwrap()))])
+    pub fn parse_co2(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 2 { return Err(GatewayError::Parse("Invalid data lenght for co2".to_string())); }
+        let co2 = u16::from_be_bytes(data[data.len() - 2..].try_into().unwrap());
+        // 0xFFFF is what a failed or warming up sensor reports
+        if co2 == 0xFFFF {
+            log::warn!("Co2 sensor reports no value (0xFFFF)");
+            return Ok(vec![SensorValue::Empty]);
+        }
+        Ok(vec![SensorValue::Co2(co2)])
     }
 
-    pub fn parse_utc(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 4 { return Err("Invalid data length for utc time".to_string()); }
+    pub fn parse_utc(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 4 { return Err(GatewayError::Parse("Invalid data length for utc time".to_string())); }
         let utc = i32::from_be_bytes([data[0], data[1], data[2], data[3]]);
         Ok(vec![SensorValue::UtcTime(utc)])
     }
 
-    pub fn parse_datetime(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 6 { return Err("Invalid data length for utc time".to_string()); }
+    pub fn parse_datetime(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 6 { return Err(GatewayError::Parse("Invalid data length for utc time".to_string())); }
         Ok(vec![SensorValue::DateTime(data.try_into().unwrap())])
     }
 
-    pub fn parse_wh45(data: &[u8]) -> Result<Vec<SensorValue>, String> {
-        if data.len() != 6 { return Err("Invalid data length for wh45 sensor".to_string()); }
+    pub fn parse_wh45(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        // temp(2) humidity(1) pm10(2) pm10_24h(2) pm25(2) pm25_24h(2) co2(2) co2_24h(2) battery(1)
+        if data.len() != 16 { return Err(GatewayError::Parse("Invalid data length for wh45 sensor".to_string())); }
     
-        let temp = Self::parse_temp(&data[0..2]).unwrap()[0];
-        let humid = Self::parse_humidity(&[data[2]]).unwrap()[0];
-        let pm10 = Self::parse_pm10(&data[3..5]).unwrap()[0];
-        let pm10_avg = Self::parse_humidity(&data[5..7]).unwrap()[0];
-        let pm25 = Self::parse_pm25(&data[7..9]).unwrap()[0];
-        let pm25_avg = Self::parse_pm25(&data[9..11]).unwrap()[0];
-        let co2 = Self::parse_co2(&data[11..13]).unwrap()[0];
-        let co2_avg = Self::parse_co2(&data[13..15]).unwrap()[0];
-        // TODO: do we need to parse battery state here
+        let temp = Self::parse_temp(&data[0..2])?.remove(0);
+        let humid = Self::parse_humidity(&[data[2]])?.remove(0);
+        let pm10 = Self::parse_pm10(&data[3..5])?.remove(0);
+        let pm10_avg = Self::parse_pm10(&data[5..7])?.remove(0);
+        let pm25 = Self::parse_pm25(&data[7..9])?.remove(0);
+        let pm25_avg = Self::parse_pm25(&data[9..11])?.remove(0);
+        let co2 = Self::parse_co2(&data[11..13])?.remove(0);
+        let co2_avg = Self::parse_co2(&data[13..15])?.remove(0);
+        // Same integer scale as the battery in the sensor id metadata for 0x27
+        let battery = SensorMetadata::parse_battery_state(0x27, Some(data[15] as f64)).unwrap_or(SensorBatteryState::Unknown);
+
+        Ok(vec![temp, humid, pm10, pm10_avg, pm25, pm25_avg, co2, co2_avg, SensorValue::Battery(battery)])
+    }
 
-        Ok(vec![temp, humid, pm10, pm10_avg, pm25, pm25_avg, co2, co2_avg])
+    // Temperature followed by the battery voltage, which is reported through the sensor metadata
+    pub fn parse_wn34(data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
+        if data.len() != 3 { return Err(GatewayError::Parse("Invalid data length for wn34".to_string())); }
+        SensorValue::parse_temp(&data[0..2])
     }
 
-    pub fn skip_data(_data: &[u8]) -> Result<Vec<SensorValue>, String> {
+    pub fn skip_data(_data: &[u8]) -> Result<Vec<SensorValue>, GatewayError> {
         Ok(vec![SensorValue::Empty])
     }
 
@@ -522,11 +1450,51 @@ impl SensorMetadata {
     fn new(type_id: u8, address: u32, battery: Option<f64>, signal: u8) -> Self {
         let type_id_str = Self::parse_type(type_id).unwrap_or("unknown".to_string());
         let type_desc = Self::parse_type_desc(type_id).unwrap_or("unknown".to_string());
-        let battery_state = Self::parse_battery_state(type_id, battery.unwrap());
+        let battery_state = Self::parse_battery_state(type_id, battery);
 
         SensorMetadata { type_id, type_desc, type_id_str, address, battery_level : battery, battery_state, signal }
     }
 
+    // Sensor that reported a live data field, the first present of the types that can report it.
+    // Outdoor arrays come before single sensors, a WH65 reports temperature, wind, rain, light
+    // and uv all by itself
+    pub fn source_of<'a>(field: &str, metadata: &'a HashMap<(u8, u32), SensorMetadata>) -> Option<&'a SensorMetadata> {
+        Self::source_types(field).into_iter()
+            .find_map(|type_id| metadata.values().filter(|meta| meta.type_id == type_id).min_by_key(|meta| meta.address))
+    }
+
+    // Sensor types that can report a live data field, in order of preference
+    pub fn source_types(field: &str) -> Vec<u8> {
+        let channel = |prefix: &str, suffix: &str, count: u8| field.strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix(suffix))
+            .and_then(|channel| channel.parse::<u8>().ok())
+            .filter(|channel| (1..=count).contains(channel));
+
+        match field {
+            "outdoor_temp" | "out_humidity" | "windchill" | "heat_index" => return vec![0x0, 0x30, 0x2, 0x5],
+            "wind_dir" | "wind_speed" | "gust_speed" | "day_maxwind" | "light" | "uv" | "uv_index" => return vec![0x0, 0x30, 0x2, 0x1],
+            "rain_event" | "rain_rate" | "rain_gain" | "rain_day" | "rain_week" | "rain_month" | "rain_year" | "rain_totals" => return vec![0x0, 0x3],
+            "indoor_temp" | "in_humidity" | "abs_barometer" | "rel_barometer" => return vec![0x4],
+            "lightning_distance" | "lightning_datetime" | "lightning_count" => return vec![0x1a],
+            _ if field.starts_with("rain_piezo_") => return vec![0x30],
+            _ if field.ends_with("_wh45") => return vec![0x27],
+            _ => {}
+        }
+
+        let channel_type = channel("temp_ch", "", 8).or(channel("humidity_", "", 8)).map(|channel| 0x5 + channel)
+            .or(channel("soil_moist_", "", 8).map(|channel| 0xd + channel))
+            .or(channel("pm25_", "", 4).or(channel("pm25_", "_avg_24h", 4)).map(|channel| 0x15 + channel))
+            .or(channel("leak", "", 4).map(|channel| 0x1a + channel))
+            .or(channel("temp_wn34_ch", "", 7).map(|channel| 0x1e + channel))
+            .or(channel("leaf_wetness_ch", "", 8).map(|channel| 0x27 + channel));
+        channel_type.into_iter().collect()
+    }
+
+    // Type id of a sensor type name like "wh65" or "wh31_ch2"
+    pub fn type_id_of(name: &str) -> Option<u8> {
+        (0..=u8::MAX).find(|id| Self::parse_type(*id).as_deref() == Some(name))
+    }
+
     fn parse_type(id: u8) -> Option<String> {
         match id {
             0x0 => Some("wh65".to_string()),
@@ -543,6 +1511,7 @@ impl SensorMetadata {
             0x1f..=0x25 => Some(format!("wh34_ch{:?}", id - 0x1e)),
             0x27 => Some("wh45".to_string()),
             0x28..=0x2f => Some(format!("wh35_ch{:?}", id - 0x27)),
+            0x30 => Some("ws90".to_string()),
             _ => None
         }
     }
@@ -563,13 +1532,43 @@ impl SensorMetadata {
             0x1f..=0x25 => Some(format!("WH-34 channel {:?}", id - 0x1e)),
             0x27 => Some("WH-45".to_string()),
             0x28..=0x2f => Some(format!("WH-35 channel {:?}", id - 0x27)),
+            0x30 => Some("WS-90".to_string()),
             _ => None
         }
     }
 
-    fn parse_battery_state(id: u8, battery: f64) -> Option<SensorBatteryState> {
+    pub fn battery_kind(&self) -> Option<BatteryKind> {
+        Self::parse_battery_kind(self.type_id)
+    }
+
+    // Numeric battery reading, bars for level sensors and volts for volt sensors
+    pub fn battery_value(&self) -> Option<f64> {
+        let battery = self.battery_level?;
+        match self.battery_kind()? {
+            BatteryKind::Binary => None,
+            BatteryKind::Level => Some(battery),
+            // WH40 and WH51 report tenths of a volt, the others 20mV steps
+            BatteryKind::Volt if self.type_id == 3 || (0xe..=0x15).contains(&self.type_id) => Some(SensorValue::round(&(battery * 0.1), SensorValue::DEFAULT_PRECISION)),
+            BatteryKind::Volt => Some(SensorValue::round(&(battery * 0.02), SensorValue::DEFAULT_PRECISION)),
+        }
+    }
+
+    fn parse_battery_kind(id: u8) -> Option<BatteryKind> {
         match id {
-            0|4|5..=0xd => {
+            0|4|5..=0xd => Some(BatteryKind::Binary),
+            0x16..=0x1e|0x27 => Some(BatteryKind::Level),
+            1..=3|0xe..=0x15|0x1f..=0x26|0x28..=0x30 => Some(BatteryKind::Volt),
+            _ => None,
+        }
+    }
+
+    fn parse_battery_state(id: u8, battery: Option<f64>) -> Option<SensorBatteryState> {
+        let Some(battery) = battery else {
+            return Some(SensorBatteryState::Unknown);
+        };
+
+        match Self::parse_battery_kind(id) {
+            Some(BatteryKind::Binary) => {
                 log::trace!("Binary battery: id {:#x?} {:?} volt", id, battery);
                 // Binary
                 if battery == 1.0 {
@@ -580,7 +1579,7 @@ impl SensorMetadata {
                     Some(SensorBatteryState::Unknown) 
                 }
             }
-            0x16..=0x1e|0x27 => {
+            Some(BatteryKind::Level) => {
                 log::trace!("Integer battery: id {:#x?} {:?} volt", id, battery);
                 // Integer
                 if battery <= 1.0 { 
@@ -593,7 +1592,7 @@ impl SensorMetadata {
                     Some(SensorBatteryState::Unknown) 
                 }
             }
-            1..=3|0xe..=0x15|0x1f..=0x26|0x28..=0x30 => {
+            Some(BatteryKind::Volt) => {
                 log::trace!("Volt battery: id {:#x?} {:?} volt", id, battery);
                 // Volt
                 if battery <= 1.2 { 
@@ -602,16 +1601,38 @@ impl SensorMetadata {
                     Some(SensorBatteryState::Ok) 
                 }
             }
-            _ => Some(SensorBatteryState::Unknown)
+            None => Some(SensorBatteryState::Unknown)
         }
     } 
 }
 
+impl Default for Sensors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Sensors {
     pub fn new() -> Self {
         Sensors {
             parsers : Self::init_parsers(),
+            unknown_types: Mutex::new(HashSet::new()),
+            duplicate_addresses: Mutex::new(HashSet::new()),
+            raw_fields: HashMap::new(),
+        }
+    }
+
+    // Emit fields of `type_id` as raw values of `size` bytes, for fields the parser doesn't know yet
+    pub fn add_raw_field(&mut self, type_id: u8, size: usize) -> Result<(), String> {
+        if self.parsers.contains_key(&type_id) {
+            return Err(format!("Type id {:#04x} already has a parser", type_id));
+        }
+        if size == 0 {
+            return Err(format!("Size of raw type id {:#04x} must not be 0", type_id));
         }
+
+        self.raw_fields.insert(type_id, (format!("raw_{:02x}", type_id), size));
+        Ok(())
     }
 
     fn init_parsers() -> HashMap<u8, ParseInfo<'static>> {
@@ -629,8 +1650,7 @@ impl Sensors {
         parsers.insert(0x0B, ParseInfo { parse_fn: SensorValue::parse_speed, field_names: vec!["wind_speed"], size: 2});
         parsers.insert(0x0C, ParseInfo { parse_fn: SensorValue::parse_speed, field_names: vec!["gust_speed"], size: 2});
         parsers.insert(0x0D, ParseInfo { parse_fn: SensorValue::parse_rain, field_names: vec!["rain_event"], size: 2});
-        // TODO: rain rate special?
-        parsers.insert(0x0E, ParseInfo { parse_fn: SensorValue::parse_rain, field_names: vec!["rain_rate"], size: 2});
+        parsers.insert(0x0E, ParseInfo { parse_fn: SensorValue::parse_rain_rate, field_names: vec!["rain_rate"], size: 2});
         parsers.insert(0x0F, ParseInfo { parse_fn: SensorValue::parse_gain, field_names: vec!["rain_gain"], size: 2});
         parsers.insert(0x10, ParseInfo { parse_fn: SensorValue::parse_rain, field_names: vec!["rain_day"], size: 2});
         parsers.insert(0x11, ParseInfo { parse_fn: SensorValue::parse_rain, field_names: vec!["rain_week"], size: 2});
@@ -642,22 +1662,23 @@ impl Sensors {
         parsers.insert(0x17, ParseInfo { parse_fn: SensorValue::parse_uv_index, field_names: vec!["uv_index"], size: 1});
         parsers.insert(0x18, ParseInfo { parse_fn: SensorValue::parse_datetime, field_names: vec!["datetime"], size: 6});
         parsers.insert(0x19, ParseInfo { parse_fn: SensorValue::parse_speed, field_names: vec!["day_maxwind"], size: 2});
-        parsers.insert(0x1A, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_1"], size: 2});
-        parsers.insert(0x1B, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_2"], size: 2});
-        parsers.insert(0x1C, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_3"], size: 2});
-        parsers.insert(0x1D, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_4"], size: 2});
-        parsers.insert(0x1E, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_5"], size: 2});
-        parsers.insert(0x1F, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_6"], size: 2});
-        parsers.insert(0x20, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_7"], size: 2});
-        parsers.insert(0x21, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_8"], size: 2});
-        parsers.insert(0x22, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["humidity_1"], size: 1});
-        parsers.insert(0x23, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["humidity_2"], size: 1});
-        parsers.insert(0x24, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["humidity_3"], size: 1});
-        parsers.insert(0x25, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["humidity_4"], size: 1});
-        parsers.insert(0x26, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["humidity_5"], size: 1});
-        parsers.insert(0x27, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["humidity_6"], size: 1});
-        parsers.insert(0x28, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["humidity_7"], size: 1});
-        parsers.insert(0x29, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["humidity_8"], size: 1});
+        // WH31 multi channel temperature and humidity, channel 1-8
+        parsers.insert(0x1A, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_ch1"], size: 2});
+        parsers.insert(0x1B, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_ch2"], size: 2});
+        parsers.insert(0x1C, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_ch3"], size: 2});
+        parsers.insert(0x1D, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_ch4"], size: 2});
+        parsers.insert(0x1E, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_ch5"], size: 2});
+        parsers.insert(0x1F, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_ch6"], size: 2});
+        parsers.insert(0x20, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_ch7"], size: 2});
+        parsers.insert(0x21, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["temp_ch8"], size: 2});
+        parsers.insert(0x22, ParseInfo { parse_fn: SensorValue::parse_humidity, field_names: vec!["humidity_1"], size: 1});
+        parsers.insert(0x23, ParseInfo { parse_fn: SensorValue::parse_humidity, field_names: vec!["humidity_2"], size: 1});
+        parsers.insert(0x24, ParseInfo { parse_fn: SensorValue::parse_humidity, field_names: vec!["humidity_3"], size: 1});
+        parsers.insert(0x25, ParseInfo { parse_fn: SensorValue::parse_humidity, field_names: vec!["humidity_4"], size: 1});
+        parsers.insert(0x26, ParseInfo { parse_fn: SensorValue::parse_humidity, field_names: vec!["humidity_5"], size: 1});
+        parsers.insert(0x27, ParseInfo { parse_fn: SensorValue::parse_humidity, field_names: vec!["humidity_6"], size: 1});
+        parsers.insert(0x28, ParseInfo { parse_fn: SensorValue::parse_humidity, field_names: vec!["humidity_7"], size: 1});
+        parsers.insert(0x29, ParseInfo { parse_fn: SensorValue::parse_humidity, field_names: vec!["humidity_8"], size: 1});
         parsers.insert(0x2A, ParseInfo { parse_fn: SensorValue::parse_pm25, field_names: vec!["pm25_1"], size: 2});
         
         parsers.insert(0x2B, ParseInfo { parse_fn: SensorValue::parse_temp, field_names: vec!["soil_temp_1"], size: 2});
@@ -698,55 +1719,167 @@ impl Sensors {
         parsers.insert(0x61, ParseInfo { parse_fn: SensorValue::parse_utc, field_names: vec!["lightning_datetime"], size: 4});
         parsers.insert(0x62, ParseInfo { parse_fn: SensorValue::parse_count, field_names: vec!["lightning_count"], size: 4});
 
-        parsers.insert(0x70, ParseInfo { parse_fn: SensorValue::parse_wh45, field_names: vec!["temp_wh45", "humid_wh45", "pm10_wh45", "pm10_avg_24h_wh45", "pm25_wh45", "pm25_avg_24h_wh45", "co2_wh45", "co2_avg_24h_wh45"], size:16});
+        // WN34 external temperature probes, channel 1-8
+        parsers.insert(0x63, ParseInfo { parse_fn: SensorValue::parse_wn34, field_names: vec!["temp_wn34_ch1"], size: 3});
+        parsers.insert(0x64, ParseInfo { parse_fn: SensorValue::parse_wn34, field_names: vec!["temp_wn34_ch2"], size: 3});
+        parsers.insert(0x65, ParseInfo { parse_fn: SensorValue::parse_wn34, field_names: vec!["temp_wn34_ch3"], size: 3});
+        parsers.insert(0x66, ParseInfo { parse_fn: SensorValue::parse_wn34, field_names: vec!["temp_wn34_ch4"], size: 3});
+        parsers.insert(0x67, ParseInfo { parse_fn: SensorValue::parse_wn34, field_names: vec!["temp_wn34_ch5"], size: 3});
+        parsers.insert(0x68, ParseInfo { parse_fn: SensorValue::parse_wn34, field_names: vec!["temp_wn34_ch6"], size: 3});
+        parsers.insert(0x69, ParseInfo { parse_fn: SensorValue::parse_wn34, field_names: vec!["temp_wn34_ch7"], size: 3});
+        parsers.insert(0x6A, ParseInfo { parse_fn: SensorValue::parse_wn34, field_names: vec!["temp_wn34_ch8"], size: 3});
+
+        parsers.insert(0x70, ParseInfo { parse_fn: SensorValue::parse_wh45, field_names: vec!["temp_wh45", "humid_wh45", "pm10_wh45", "pm10_avg_24h_wh45", "pm25_wh45", "pm25_avg_24h_wh45", "co2_wh45", "co2_avg_24h_wh45", "battery_wh45"], size:16});
+
+        // WH35 leaf wetness in %, channel 1-8. The sensor id metadata uses 0x28-0x2f for the same
+        // sensors, those ids are a separate numbering from the live data ids
+        parsers.insert(0x72, ParseInfo { parse_fn: SensorValue::parse_leaf_wetness, field_names: vec!["leaf_wetness_ch1"], size: 1});
+        parsers.insert(0x73, ParseInfo { parse_fn: SensorValue::parse_leaf_wetness, field_names: vec!["leaf_wetness_ch2"], size: 1});
+        parsers.insert(0x74, ParseInfo { parse_fn: SensorValue::parse_leaf_wetness, field_names: vec!["leaf_wetness_ch3"], size: 1});
+        parsers.insert(0x75, ParseInfo { parse_fn: SensorValue::parse_leaf_wetness, field_names: vec!["leaf_wetness_ch4"], size: 1});
+        parsers.insert(0x76, ParseInfo { parse_fn: SensorValue::parse_leaf_wetness, field_names: vec!["leaf_wetness_ch5"], size: 1});
+        parsers.insert(0x77, ParseInfo { parse_fn: SensorValue::parse_leaf_wetness, field_names: vec!["leaf_wetness_ch6"], size: 1});
+        parsers.insert(0x78, ParseInfo { parse_fn: SensorValue::parse_leaf_wetness, field_names: vec!["leaf_wetness_ch7"], size: 1});
+        parsers.insert(0x79, ParseInfo { parse_fn: SensorValue::parse_leaf_wetness, field_names: vec!["leaf_wetness_ch8"], size: 1});
+
+        // WS90 rain source priority and radiation compensation
+        parsers.insert(0x7A, ParseInfo { parse_fn: SensorValue::skip_data, field_names: vec![""], size: 1});
+        parsers.insert(0x7B, ParseInfo { parse_fn: SensorValue::skip_data, field_names: vec![""], size: 1});
+
+        // WS90 piezo rain
+        parsers.insert(0x80, ParseInfo { parse_fn: SensorValue::parse_rain_rate, field_names: vec!["rain_piezo_rate"], size: 2});
+        parsers.insert(0x81, ParseInfo { parse_fn: SensorValue::parse_rain, field_names: vec!["rain_piezo_event"], size: 2});
+        parsers.insert(0x82, ParseInfo { parse_fn: SensorValue::parse_rain, field_names: vec!["rain_piezo_hour"], size: 2});
+        parsers.insert(0x83, ParseInfo { parse_fn: SensorValue::parse_rainlarge, field_names: vec!["rain_piezo_day"], size: 4});
+        parsers.insert(0x84, ParseInfo { parse_fn: SensorValue::parse_rainlarge, field_names: vec!["rain_piezo_week"], size: 4});
+        parsers.insert(0x85, ParseInfo { parse_fn: SensorValue::parse_rainlarge, field_names: vec!["rain_piezo_month"], size: 4});
+        parsers.insert(0x86, ParseInfo { parse_fn: SensorValue::parse_rainlarge, field_names: vec!["rain_piezo_year"], size: 4});
+        // Skip piezo gain (10 x 2 bytes) and rain reset times
+        parsers.insert(0x87, ParseInfo { parse_fn: SensorValue::skip_data, field_names: vec![""], size: 20});
+        parsers.insert(0x88, ParseInfo { parse_fn: SensorValue::skip_data, field_names: vec![""], size: 3});
 
         parsers
     }
 
-    pub fn update_metadata(&self, id_data: &[u8]) -> Result<HashMap<u32, SensorMetadata>, String> {
+    // Every live data field that can be decoded as (type id, field name, size of the type's data),
+    // sorted by type id. Types with several values list each field.
+    pub fn known_fields(&self) -> Vec<(u8, &'static str, usize)> {
+        let mut fields: Vec<(u8, &'static str, usize)> = self.parsers.iter()
+            .flat_map(|(&type_id, parser)| parser.field_names.iter()
+                .filter(|name| !name.is_empty())
+                .map(move |&name| (type_id, name, parser.size)))
+            .collect();
+        fields.sort();
+        fields
+    }
+
+    pub fn field_names(&self) -> Vec<String> {
+        self.known_fields().into_iter().map(|(_, name, _)| name.to_string())
+            .chain(self.raw_fields.values().map(|(name, _)| name.clone()))
+            .collect()
+    }
+
+    // Sensor ids in the 0x3c response, seven byte records of type, address, battery and signal
+    pub fn update_metadata(&self, id_data: &[u8]) -> Result<HashMap<(u8, u32), SensorMetadata>, GatewayError> {
+        if id_data.is_empty() {
+            return Ok(HashMap::new());
+        }
+        self.parse_metadata_records(Self::sensor_id_payload(id_data, true)?, 7)
+    }
+
+    // Sensor ids in the legacy 0x3a response, six byte records without the signal
+    pub fn update_metadata_legacy(&self, id_data: &[u8]) -> Result<HashMap<(u8, u32), SensorMetadata>, GatewayError> {
+        if id_data.is_empty() {
+            return Ok(HashMap::new());
+        }
+        self.parse_metadata_records(Self::sensor_id_payload(id_data, false)?, 6)
+    }
+
+    // Records of a sensor id response, the 0x3c response has a two byte size field and 0x3a one byte
+    fn sensor_id_payload(id_data: &[u8], wide_size: bool) -> Result<&[u8], GatewayError> {
+        let header = if wide_size { 5 } else { 4 };
+        if id_data.len() < header {
+            log::debug!("Sensor id response too short, {} bytes", id_data.len());
+            return Err(GatewayError::ShortPayload);
+        }
+
+        let data_size = if wide_size {
+            u16::from_be_bytes([id_data[3], id_data[4]]) as usize
+        } else {
+            id_data[3] as usize
+        };
+
+        // Declared size covers command, size, data and checksum
+        let overhead = header - 1;
+        if data_size < overhead || header + data_size - overhead > id_data.len() {
+            log::debug!("Sensor id response declares {} bytes but only {} were received", data_size + 2, id_data.len());
+            return Err(GatewayError::ShortPayload);
+        }
+
+        Ok(&id_data[header..(header + data_size - overhead)])
+    }
+
+    fn parse_metadata_records(&self, data: &[u8], record_size: usize) -> Result<HashMap<(u8, u32), SensorMetadata>, GatewayError> {
         let mut metadata = HashMap::new();
-        if !id_data.is_empty() {
-            let data_size_bytes: [u8; 2] = id_data[3..5].try_into().expect("Failed to convert data to array");
-            let data_size = u16::from_be_bytes(data_size_bytes);
-
-            // Extract the actual sensor ID data.
-            let data = &id_data[5..(5 + data_size as usize - 4)];
-
-            // Initialize a counter.
-            let mut index = 0;
-
-            // Iterate over the data.
-            while index < data.len() {
-                let type_id: u8 = data[index];
-                let sensor_id_bytes: [u8; 4] = data[(index + 1)..(index + 5)]
-                        .try_into()
-                        .expect("Failed to convert sensor ID bytes");
-                
-                let address = u32::from_be_bytes(sensor_id_bytes);
-                let batt = data[index + 5];
-                let signal = data[index + 6];
+        let mut index = 0;
 
-                log::trace!("Metadata type={} address:{} battery:{} signal:{}", type_id, address, batt, signal);
+        // Iterate over the data.
+        while index < data.len() {
+            if index + record_size > data.len() {
+                log::debug!("Truncated sensor id record at offset {}, {} bytes left", index, data.len() - index);
+                return Err(GatewayError::ShortPayload);
+            }
 
-                // check if the sensor is active or not
-                if address != 0xffffffff {
-                    let meta = SensorMetadata::new(type_id, address, Some(f64::from(batt)), signal);
-                    log::debug!("Meta={:?}", meta);
-                    if meta.type_id_str.eq("unknown") {
+            let type_id: u8 = data[index];
+            let sensor_id_bytes: [u8; 4] = data[(index + 1)..(index + 5)]
+                    .try_into()
+                    .expect("Failed to convert sensor ID bytes");
+            
+            let address = u32::from_be_bytes(sensor_id_bytes);
+            let batt = data[index + 5];
+            // The legacy records have no signal
+            let signal = if record_size > 6 { data[index + 6] } else { 0 };
+
+            log::trace!("Metadata type={} address:{} battery:{} signal:{}", type_id, address, batt, signal);
+
+            // check if the sensor is active or not
+            if address != 0xffffffff {
+                let meta = SensorMetadata::new(type_id, address, Some(f64::from(batt)), signal);
+                log::debug!("Meta={:?}", meta);
+                if meta.type_id_str.eq("unknown") {
+                    // Metadata is refreshed every poll, only warn the first time a type shows up
+                    if self.unknown_types.lock().unwrap().insert(type_id) {
                         log::warn!("Found unknown sensor {:?}", meta);
+                    } else {
+                        log::debug!("Found unknown sensor {:?}", meta);
+                    }
+                }
+                
+                // A half paired sensor can show up with the address of another one, keep both
+                if let Some(other) = metadata.keys().find(|(other_type, other_address)| *other_address == address && *other_type != type_id) {
+                    if self.duplicate_addresses.lock().unwrap().insert(address) {
+                        log::warn!("Sensor types {:#04x} and {:#04x} both report address {:#010x}", other.0, type_id, address);
+                    } else {
+                        log::debug!("Sensor types {:#04x} and {:#04x} both report address {:#010x}", other.0, type_id, address);
                     }
-                    
-                    metadata.insert(address, meta);
                 }
 
-                // Each sensor entry is seven bytes in length, so skip to the start of the next sensor.
-                index += 7;
+                metadata.insert((type_id, address), meta);
             }
-        } 
+
+            // Skip to the start of the next sensor.
+            index += record_size;
+        }
         Ok(metadata)
     }
 
-    pub fn parse_live_data(&self, data: &[u8]) -> Result<Vec<Vec<SensorData>>, String> {
+    pub fn parse_live_data(&self, data: &[u8]) -> Result<Vec<Vec<SensorData>>, GatewayError> {
+        self.parse_live_data_partial(data).map(|(sensor_data, _)| sensor_data)
+    }
+
+    // Like parse_live_data, also reporting if the data ended in the middle of a field. The
+    // fields before it are still returned.
+    pub fn parse_live_data_partial(&self, data: &[u8]) -> Result<(Vec<Vec<SensorData>>, bool), GatewayError> {
         let mut sensor_data: Vec<Vec<SensorData>> = Vec::new();
 
         let mut index = 0;
@@ -755,29 +1888,727 @@ impl Sensors {
             let type_id = data[index];
             if let Some(&parser) = self.parsers.get(&type_id).as_ref() {
                 log::trace!("Found type {:#x}", type_id);
-                if index + 1 + parser.size <= data.len() {
-                    // Some sensors can have multiple fields/values, hard coded order
-                    // in the parser setup
-                    let field_data = data[index + 1..index + 1 + parser.size].to_vec();
-                    if let Ok(parsed_data) = (parser.parse_fn)(&field_data) {
-                        let mut values = Vec::new();
-                        for i in 0..parsed_data.len() {
-                            let val = parsed_data[i];
-                            let name = parser.field_names[i];
-
-                            log::trace!("field: {:?} val:{:?}", name, val);
-                            values.push(SensorData::new(name, val));
-                        }
+                if index + 1 + parser.size > data.len() {
+                    log::warn!("Live data truncated in field {:#x} at offset {}, {} of {} bytes left, dropping it",
+                               type_id, index, data.len() - index - 1, parser.size);
+                    return Ok((sensor_data, true));
+                }
+
+                // Some sensors can have multiple fields/values, hard coded order
+                // in the parser setup
+                let field_data = data[index + 1..index + 1 + parser.size].to_vec();
+                if let Ok(parsed_data) = (parser.parse_fn)(&field_data) {
+                    let mut values = Vec::new();
+                    for (val, name) in parsed_data.into_iter().zip(parser.field_names.iter()) {
+                        log::trace!("field: {:?} val:{:?}", name, val);
+                        values.push(SensorData::new(name, val));
+                    }
 
-                        sensor_data.push(values);
-                    }               
+                    sensor_data.push(values);
+                }               
+                index += parser.size + 1;
+            } else if let Some((name, size)) = self.raw_fields.get(&type_id) {
+                if index + 1 + size > data.len() {
+                    log::warn!("Live data truncated in raw field {:#x} at offset {}, {} of {} bytes left, dropping it",
+                               type_id, index, data.len() - index - 1, size);
+                    return Ok((sensor_data, true));
                 }
-                index += parser.size as usize + 1;
+
+                sensor_data.push(vec![SensorData::new(name, SensorValue::Raw(data[index + 1..index + 1 + size].to_vec()))]);
+                index += size + 1;
             } else {
-                return Err(format!("Failed to find parser for type id {:#x}", type_id));
+                // The field length is unknown, so the rest of the frame can't be parsed
+                log::warn!("Failed to find parser for type id {:#x} at offset {}, skipping rest of live data. Add it to config.raw_fields with its size to keep parsing", type_id, index);
+                break;
             }
         }
 
-        Ok(sensor_data)
+        Ok((sensor_data, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::transport::MockTransport;
+
+    // Gateway answering from canned payloads by command, without waits between retries. The
+    // transport handle shows what was sent
+    fn mock_gateway(responses: &[(GatewayCommands, &[u8])]) -> (SensorGateway, Arc<MockTransport>) {
+        let transport = Arc::new(MockTransport::new());
+        for (cmd, payload) in responses {
+            transport.respond(*cmd as u8, payload);
+        }
+        let options = ConnectionOptions { retry_wait: Duration::ZERO, max_retry_wait: Duration::ZERO, ..ConnectionOptions::default() };
+        let gateway = SensorGateway::build("127.0.0.1:45000".parse().unwrap(), Box::new(transport.clone()), options, None);
+        (gateway, transport)
+    }
+
+    #[test_log::test]
+    fn send_cmd_returns_validated_response() {
+        let (gateway, transport) = mock_gateway(&[(GatewayCommands::ReadFirmwareVersion, b"\x04V1.0")]);
+
+        assert_eq!(gateway.get_firmware_version().unwrap(), "V1.0");
+        assert_eq!(transport.sent(), vec![vec![0xFF, 0xFF, 0x50, 0x03, 0x53]]);
+    }
+
+    #[test_log::test]
+    fn send_cmd_retries_bad_checksum() {
+        let (gateway, transport) = mock_gateway(&[]);
+        let mut frame = MockTransport::frame(GatewayCommands::ReadFirmwareVersion as u8, b"\x04V1.0");
+        *frame.last_mut().unwrap() ^= 0xFF;
+        transport.respond_raw(GatewayCommands::ReadFirmwareVersion as u8, frame);
+
+        assert!(matches!(gateway.get_firmware_version(), Err(GatewayError::Checksum { .. })));
+        assert_eq!(transport.sent().len(), 3);
+    }
+
+    #[test_log::test]
+    fn send_cmd_rejects_other_command() {
+        let (gateway, transport) = mock_gateway(&[]);
+        transport.respond_raw(GatewayCommands::ReadFirmwareVersion as u8, MockTransport::frame(0x26, &[0; 6]));
+
+        assert!(matches!(gateway.get_firmware_version(), Err(GatewayError::BadCommand { expected: 0x50, got: 0x26 })));
+    }
+
+    #[test_log::test]
+    fn send_cmd_gives_up_without_response() {
+        let (gateway, transport) = mock_gateway(&[]);
+
+        match gateway.get_firmware_version() {
+            Err(GatewayError::Io(err)) => assert_eq!(err.kind(), std::io::ErrorKind::TimedOut),
+            other => panic!("Expected a timeout, got {:?}", other),
+        }
+        assert_eq!(transport.sent().len(), 3);
+    }
+
+    #[test_log::test]
+    fn parses_humidity_channels() {
+        let data: Vec<u8> = (0..8u8).flat_map(|channel| [0x22 + channel, 40 + channel]).collect();
+
+        let sensors = Sensors::new().parse_live_data(&data).unwrap();
+        let humidity: Vec<(String, f64)> = sensors.iter().flatten()
+            .map(|sensor| match sensor.value() {
+                SensorValue::Humidity(val) => (sensor.name().to_string(), *val),
+                other => panic!("Expected humidity for {}, got {:?}", sensor.name(), other),
+            })
+            .collect();
+        let expected: Vec<(String, f64)> = (0..8).map(|channel| (format!("humidity_{}", channel + 1), 40.0 + channel as f64)).collect();
+        assert_eq!(humidity, expected);
+    }
+
+    #[test_log::test]
+    fn converts_to_imperial_units() {
+        let imperial = |value: SensorValue| value.to_json_val_with_units(UnitSystem::Imperial);
+
+        assert_eq!(imperial(SensorValue::Temp(20.0)), json!(68.0));
+        assert_eq!(imperial(SensorValue::Temp(-40.0)), json!(-40.0));
+        assert_eq!(imperial(SensorValue::Pressure(1013.2)), json!(29.92));
+        assert_eq!(imperial(SensorValue::Speed(10.0)), json!(22.37));
+        assert_eq!(imperial(SensorValue::Rain(25.4)), json!(1.0));
+        assert_eq!(imperial(SensorValue::RainLarge(127.0)), json!(5.0));
+        assert_eq!(imperial(SensorValue::Distance(10)), json!(6.21));
+        assert_eq!(imperial(SensorValue::Humidity(55.0)), json!(55.0));
+        assert_eq!(SensorValue::Temp(20.0).to_json_val_with_units(UnitSystem::Metric), json!(20.0));
+    }
+
+    #[test_log::test]
+    fn maps_units_of_measurement() {
+        assert_eq!(UnitSystem::Imperial.unit_of_measurement("°C"), "°F");
+        assert_eq!(UnitSystem::Imperial.unit_of_measurement("hPa"), "inHg");
+        assert_eq!(UnitSystem::Imperial.unit_of_measurement("m/s"), "mph");
+        assert_eq!(UnitSystem::Imperial.unit_of_measurement("mm"), "in");
+        assert_eq!(UnitSystem::Imperial.unit_of_measurement("%"), "%");
+        assert_eq!(UnitSystem::Metric.unit_of_measurement("°C"), "°C");
+    }
+
+    #[test_log::test]
+    fn parses_rain_rate_as_rate() {
+        let sensors = Sensors::new().parse_live_data(&[0x0E, 0x00, 0x7F]).unwrap();
+
+        let sensor = &sensors[0][0];
+        assert_eq!(sensor.name(), "rain_rate");
+        assert!(matches!(sensor.value(), SensorValue::RainRate(val) if *val == 12.7));
+        assert_eq!(sensor.value().to_json_val_with_units(UnitSystem::Imperial), json!(0.5));
+        assert_eq!(UnitSystem::Imperial.unit_of_measurement("mm/h"), "in/h");
+    }
+
+    #[test_log::test]
+    fn names_gateway_without_mac_or_firmware() {
+        let (gateway, _) = mock_gateway(&[]);
+
+        let expected = if cfg!(debug_assertions) { "gw_127_0_0_1_debug" } else { "gw_127_0_0_1" };
+        assert_eq!(gateway.name(), expected);
+        assert_eq!(gateway.firmware(), "unknown");
+        assert_eq!(gateway.version(), "unknown");
+        // The failed reads are kept, the name doesn't change once the gateway answers
+        assert_eq!(gateway.name(), expected);
+    }
+
+    #[test_log::test]
+    fn rejects_sensor_ids_shorter_than_declared() {
+        let mut frame = MockTransport::frame(GatewayCommands::ReadSensorIdNew as u8, &[0x00, 0x00, 0x00, 0x00, 0x12, 0x03, 0x04]);
+        // Declare two more records than the frame carries
+        frame[4] += 14;
+
+        assert!(matches!(Sensors::new().update_metadata(&frame), Err(GatewayError::ShortPayload)));
+        assert!(matches!(Sensors::new().update_metadata(&frame[..4]), Err(GatewayError::ShortPayload)));
+    }
+
+    #[test_log::test]
+    fn parses_ws90_piezo_rain() {
+        let data = [
+            0x7A, 0x01,
+            0x80, 0x00, 0x19,
+            0x81, 0x00, 0x32,
+            0x83, 0x00, 0x00, 0x00, 0x64,
+            0x86, 0x00, 0x00, 0x27, 0x10,
+            0x87, 0, 100, 0, 100, 0, 100, 0, 100, 0, 100, 0, 100, 0, 100, 0, 100, 0, 100, 0, 100,
+            0x01, 0x00, 0xE6,
+        ];
+
+        let sensors = Sensors::new().parse_live_data(&data).unwrap();
+        let values: Vec<(&str, Option<f64>)> = sensors.iter().flatten()
+            .filter(|sensor| !sensor.name().is_empty())
+            .map(|sensor| (sensor.name(), sensor.value().as_f64()))
+            .collect();
+        assert_eq!(values, vec![
+            ("rain_piezo_rate", Some(2.5)),
+            ("rain_piezo_event", Some(5.0)),
+            ("rain_piezo_day", Some(10.0)),
+            ("rain_piezo_year", Some(1000.0)),
+            ("indoor_temp", Some(23.0)),
+        ]);
+    }
+
+    #[test_log::test]
+    fn remembers_unknown_sensor_types() {
+        let sensors = Sensors::new();
+        let frame = MockTransport::frame(GatewayCommands::ReadSensorIdNew as u8, &[0x31, 0x00, 0x00, 0x00, 0x01, 0x03, 0x04]);
+
+        for _ in 0..2 {
+            let metadata = sensors.update_metadata(&frame).unwrap();
+            assert_eq!(metadata[&(0x31, 1)].type_id_str, "unknown");
+        }
+        // Warned about once, later polls only log at debug
+        assert_eq!(*sensors.unknown_types.lock().unwrap(), HashSet::from([0x31]));
+    }
+
+    #[test_log::test]
+    fn parses_wh45() {
+        let data = [0x00, 0xD7, 0x37, 0x00, 0x0C, 0x00, 0x0F, 0x00, 0x2D, 0x00, 0x32, 0x01, 0xF4, 0x01, 0xC2, 0x04];
+
+        let values = SensorValue::parse_wh45(&data).unwrap();
+        let numbers: Vec<Option<f64>> = values.iter().map(SensorValue::as_f64).collect();
+        assert_eq!(numbers, vec![Some(21.5), Some(55.0), Some(1.2), Some(1.5), Some(4.5), Some(5.0), Some(500.0), Some(450.0), None]);
+        assert!(matches!(values[3], SensorValue::Pm10(_)));
+        assert!(matches!(values[5], SensorValue::Pm25(_)));
+        assert_eq!(values[8].as_battery(), Some(SensorBatteryState::Ok));
+        assert!(SensorValue::parse_wh45(&data[..15]).is_err());
+
+        // A failed co2 sensor reports 0xFFFF
+        let mut failed = data;
+        failed[11..15].copy_from_slice(&[0xFF; 4]);
+        let values = SensorValue::parse_wh45(&failed).unwrap();
+        assert!(matches!(values[6], SensorValue::Empty));
+        assert!(matches!(values[7], SensorValue::Empty));
+    }
+
+    #[test_log::test]
+    fn rejects_zero_max_tries() {
+        let options = ConnectionOptions { max_tries: 0, ..ConnectionOptions::default() };
+
+        assert!(options.validate().is_err());
+        assert!(SensorGateway::with_options("127.0.0.1".to_string(), 45000, options).is_err());
+        assert!(ConnectionOptions::default().validate().is_ok());
+    }
+
+    #[test_log::test]
+    fn wh45_battery_levels() {
+        let state = |level: f64| SensorMetadata::new(0x27, 1, Some(level), 4).battery_state;
+
+        assert_eq!(state(0.0), Some(SensorBatteryState::Low));
+        assert_eq!(state(1.0), Some(SensorBatteryState::Low));
+        assert_eq!(state(2.0), Some(SensorBatteryState::Ok));
+        assert_eq!(state(5.0), Some(SensorBatteryState::Ok));
+        assert_eq!(state(6.0), Some(SensorBatteryState::Connected));
+        assert_eq!(state(7.0), Some(SensorBatteryState::Unknown));
+    }
+
+    #[test_log::test]
+    fn keeps_fields_before_unknown_type() {
+        let data = [0x01, 0x00, 0xE6, 0x06, 0x37, 0xF0, 0x12, 0x34, 0x07, 0x32];
+
+        let (sensors, truncated) = Sensors::new().parse_live_data_partial(&data).unwrap();
+        let names: Vec<&str> = sensors.iter().flatten().map(SensorData::name).collect();
+        assert_eq!(names, ["indoor_temp", "in_humidity"]);
+        assert!(!truncated);
+    }
+
+    #[test_log::test]
+    fn builds_rain_reset_payload() {
+        let rain = [12, 34, 560, 7890];
+
+        assert_eq!(SensorGateway::rain_reset_payload(rain, RainResetKind::Week).unwrap(),
+                   [0, 0, 0, 12, 0, 0, 0, 0, 0, 0, 0x02, 0x30, 0, 0, 0x1E, 0xD2]);
+        assert_eq!(SensorGateway::rain_reset_payload(rain, RainResetKind::Total).unwrap(), [0; 16]);
+        assert!(matches!(SensorGateway::rain_reset_payload(rain, RainResetKind::Event), Err(GatewayError::Unsupported(_))));
+    }
+
+    #[test_log::test]
+    fn reset_rain_writes_back_other_counters() {
+        // rate, day, week, month and year
+        let rain = [[0, 0, 0, 5], [0, 0, 0, 12], [0, 0, 0, 34], [0, 0, 0x02, 0x30], [0, 0, 0x1E, 0xD2]].concat();
+        let (gateway, transport) = mock_gateway(&[(GatewayCommands::ReadRainData, &rain), (GatewayCommands::WriteRainData, &[0x00])]);
+
+        gateway.reset_rain(RainResetKind::Day).unwrap();
+        let written = transport.sent().pop().unwrap();
+        assert_eq!(written[2], GatewayCommands::WriteRainData as u8);
+        assert_eq!(written[4..20], [0, 0, 0, 0, 0, 0, 0, 34, 0, 0, 0x02, 0x30, 0, 0, 0x1E, 0xD2]);
+    }
+
+    #[test_log::test]
+    fn poll_reuses_one_connection() {
+        use std::io::{Read, Write};
+        use std::sync::atomic::AtomicUsize;
+
+        // Gateway on a local port answering from the mock, counting the connections made
+        let connections_per_poll = |reuse: bool| {
+            let (_, mock) = mock_gateway(&[(GatewayCommands::ReadSensorIdNew, &[]), (GatewayCommands::LiveData, &[0x01, 0x00, 0xD7])]);
+            let connections = Arc::new(AtomicUsize::new(0));
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            let (served, counted) = (mock.clone(), connections.clone());
+            std::thread::spawn(move || {
+                for mut stream in listener.incoming().map_while(Result::ok) {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                    let mut packet = [0u8; 64];
+                    while let Ok(n @ 1..) = stream.read(&mut packet) {
+                        stream.write_all(&served.send_and_receive(&packet[..n]).unwrap()).unwrap();
+                    }
+                }
+            });
+
+            let transport = TcpTransport::new(address, Duration::from_secs(2)).reuse_connection(reuse);
+            let gateway = SensorGateway::build(address, Box::new(transport), ConnectionOptions::default(), None);
+            assert_eq!(gateway.poll().unwrap().sensors().count(), 1);
+            (connections.load(Ordering::SeqCst), mock.sent().len())
+        };
+
+        assert_eq!(connections_per_poll(true), (1, 2));
+        assert_eq!(connections_per_poll(false), (2, 2));
+    }
+
+    #[test_log::test]
+    fn lightning_time_as_iso8601() {
+        let values = SensorValue::parse_utc(&1_700_000_000i32.to_be_bytes()).unwrap();
+
+        assert_eq!(values[0].to_iso8601(DisplayTimeZone::Utc).as_deref(), Some("2023-11-14T22:13:20+00:00"));
+        assert_eq!(values[0].to_iso8601("+02:00".parse().unwrap()).as_deref(), Some("2023-11-15T00:13:20+02:00"));
+        // No strike recorded yet
+        assert_eq!(SensorValue::parse_utc(&[0xFF; 4]).unwrap()[0].to_iso8601(DisplayTimeZone::Utc), None);
+    }
+
+    #[test_log::test]
+    fn handles_empty_and_short_live_data() {
+        let (gateway, _) = mock_gateway(&[]);
+        let live_data = MockTransport::frame(GatewayCommands::LiveData as u8, &[0x01, 0x00, 0xD7]);
+
+        assert!(matches!(gateway.parse_live_data(&[]), Err(GatewayError::ShortPayload)));
+        assert!(matches!(gateway.parse_live_data(&live_data[..4]), Err(GatewayError::ShortPayload)));
+        // A size below the 4 bytes it counts itself would underflow the payload end
+        assert!(matches!(gateway.parse_live_data(&[0xFF, 0xFF, 0x27, 0x00, 0x03, 0x2A]), Err(GatewayError::Parse(_))));
+        // One byte short of the declared size
+        assert!(matches!(gateway.parse_live_data(&live_data[..live_data.len() - 2]), Err(GatewayError::ShortPayload)));
+
+        let (data, truncated) = gateway.parse_live_data(&MockTransport::frame(GatewayCommands::LiveData as u8, &[])).unwrap();
+        assert!(data.is_empty() && !truncated);
+        assert_eq!(gateway.parse_live_data(&live_data).unwrap().0.len(), 1);
+    }
+
+    #[test_log::test]
+    fn classifies_errors() {
+        let (gateway, transport) = mock_gateway(&[(GatewayCommands::ReadFirmwareVersion, b"\x04V1")]);
+        assert!(matches!(gateway.get_firmware_version(), Err(GatewayError::ShortPayload)));
+
+        transport.respond(GatewayCommands::ReadFirmwareVersion as u8, b"\x02\xFF\xFE");
+        assert!(matches!(gateway.get_firmware_version(), Err(GatewayError::Parse(_))));
+
+        let rain = [0u8; 20];
+        transport.respond(GatewayCommands::ReadRainData as u8, &rain);
+        transport.respond(GatewayCommands::WriteRainData as u8, &[0x01]);
+        assert!(matches!(gateway.reset_rain(RainResetKind::Day), Err(GatewayError::Rejected(_))));
+        assert!(matches!(gateway.reset_rain(RainResetKind::Event), Err(GatewayError::Unsupported(_))));
+
+        let err = GatewayError::from(std::io::Error::from(std::io::ErrorKind::ConnectionRefused));
+        assert!(matches!(err, GatewayError::Io(_)));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test_log::test]
+    fn metadata_without_battery_is_unknown() {
+        // WH31 channel 1 and WH45
+        for type_id in [0x06, 0x27] {
+            let metadata = SensorMetadata::new(type_id, 0xC4, None, 4);
+            assert_eq!(metadata.battery_state, Some(SensorBatteryState::Unknown));
+            assert_eq!(metadata.battery_level, None);
+        }
+    }
+
+    #[test_log::test]
+    fn parses_wn34_channels() {
+        // Channel 1 at -5.2°C and channel 3 at 21.5°C, each with its battery byte, then the indoor temp
+        let data = [0x63, 0xFF, 0xCC, 0x0F, 0x65, 0x00, 0xD7, 0x10, 0x01, 0x00, 0xC8];
+
+        let sensors = Sensors::new().parse_live_data(&data).unwrap();
+        let temps: Vec<(&str, Option<f64>)> = sensors.iter().flatten().map(|sensor| (sensor.name(), sensor.value().as_f64())).collect();
+        assert_eq!(temps, vec![("temp_wn34_ch1", Some(-5.2)), ("temp_wn34_ch3", Some(21.5)), ("indoor_temp", Some(20.0))]);
+    }
+
+    #[test_log::test]
+    fn accepts_alternate_response_code() {
+        let (gateway, transport) = mock_gateway(&[]);
+        let alternate = MockTransport::frame(GatewayCommands::ReadSensorIdNew as u8, &[]);
+        transport.respond_raw(GatewayCommands::ReadSensorId as u8, alternate.clone());
+
+        let accepted = [GatewayCommands::ReadSensorId as u8, GatewayCommands::ReadSensorIdNew as u8];
+        assert_eq!(gateway.send_cmd_expecting(&GatewayCommands::ReadSensorId, &[], &accepted).unwrap(), alternate);
+        assert!(matches!(gateway.send_cmd(&GatewayCommands::ReadSensorId, &[]), Err(GatewayError::BadCommand { expected: 0x3A, got: 0x3C })));
+
+        // An accepted code still needs a valid checksum
+        let mut corrupt = alternate;
+        *corrupt.last_mut().unwrap() ^= 0xFF;
+        assert!(matches!(SensorGateway::validate_response(&corrupt, &accepted), Err(GatewayError::Checksum { .. })));
+    }
+
+    #[test_log::test]
+    fn backoff_doubles_up_to_max() {
+        let (base, max) = (Duration::from_millis(500), Duration::from_secs(3));
+
+        let delays: Vec<Duration> = (0..5).map(|attempt| SensorGateway::backoff_delay(base, max, attempt, 0)).collect();
+        assert_eq!(delays, [500, 1000, 2000, 3000, 3000].map(Duration::from_millis));
+        // Jitter takes off up to 24%
+        assert_eq!(SensorGateway::backoff_delay(base, max, 1, 10), Duration::from_millis(900));
+        assert_eq!(SensorGateway::backoff_delay(base, max, 1, 124), Duration::from_millis(760));
+        assert_eq!(SensorGateway::backoff_delay(base, max, 40, 0), max);
+    }
+
+    #[test_log::test]
+    fn known_fields_cover_parsers() {
+        let sensors = Sensors::new();
+        let fields = sensors.known_fields();
+
+        let ids: HashSet<u8> = fields.iter().map(|(type_id, _, _)| *type_id).collect();
+        let named: HashSet<u8> = sensors.parsers.iter()
+            .filter(|(_, parser)| parser.field_names.iter().any(|name| !name.is_empty()))
+            .map(|(type_id, _)| *type_id)
+            .collect();
+        assert_eq!(ids, named);
+        assert!(ids.contains(&0x01) && ids.contains(&0x70));
+
+        let mut names: Vec<&str> = fields.iter().map(|(_, name, _)| *name).collect();
+        names.sort();
+        let count = names.len();
+        names.dedup();
+        assert_eq!(names.len(), count, "Field names reported by more than one parser");
+    }
+
+    #[test_log::test]
+    fn parses_leaf_wetness_channels() {
+        let data = [0x72, 35, 0x79, 100];
+
+        let sensors = Sensors::new().parse_live_data(&data).unwrap();
+        let values: Vec<(&str, &SensorValue)> = sensors.iter().flatten().map(|sensor| (sensor.name(), sensor.value())).collect();
+        assert!(matches!(values[..], [("leaf_wetness_ch1", SensorValue::LeafWetness(35.0)), ("leaf_wetness_ch8", SensorValue::LeafWetness(100.0))]));
+
+        // WH35 sensor ids follow the WH51 channels in the metadata
+        assert_eq!(SensorMetadata::parse_type(0x28).as_deref(), Some("wh35_ch1"));
+        assert_eq!(SensorMetadata::source_types("leaf_wetness_ch8"), vec![0x2F]);
+    }
+
+    #[test_log::test]
+    fn rejects_corrupted_header() {
+        let (gateway, transport) = mock_gateway(&[]);
+        let mut frame = MockTransport::frame(GatewayCommands::ReadFirmwareVersion as u8, b"\x04V1.0");
+        frame[1] = 0x7F;
+        transport.respond_raw(GatewayCommands::ReadFirmwareVersion as u8, frame);
+
+        match gateway.get_firmware_version() {
+            Err(err @ GatewayError::BadHeader(_)) => assert!(err.to_string().contains("received FF 7F"), "{}", err),
+            other => panic!("Expected a bad header, got {:?}", other),
+        }
+        assert!(matches!(SensorGateway::validate_response(&[0xFF], &[0x50]), Err(GatewayError::ShortPayload)));
+    }
+
+    #[test_log::test]
+    fn parses_system_info() {
+        // 868 MHz, WH65, 2023-11-14 22:13:20 UTC, time zone 39 with dst
+        let (gateway, _) = mock_gateway(&[(GatewayCommands::ReadSystemParams, &[0x01, 0x01, 0x65, 0x53, 0xF1, 0x00, 0x27, 0x01])]);
+
+        assert_eq!(gateway.system_info(), Some(&SystemInfo {
+            frequency: 868,
+            sensor_type: "WH65".to_string(),
+            utc_time: 1_700_000_000,
+            timezone_index: 39,
+            dst: true,
+        }));
+        assert!(matches!(SensorGateway::parse_system_info(&MockTransport::frame(0x30, &[0x07, 0x00, 0, 0, 0, 0, 0, 0])), Err(GatewayError::Parse(_))));
+        assert!(matches!(SensorGateway::parse_system_info(&MockTransport::frame(0x30, &[0x01, 0x00])), Err(GatewayError::ShortPayload)));
+    }
+
+    #[test_log::test]
+    fn keeps_fields_before_truncated_field() {
+        // Indoor temp, then a pressure field missing its last byte
+        let (gateway, _) = mock_gateway(&[(GatewayCommands::LiveData, &[0x01, 0x00, 0xD7, 0x08, 0x27])]);
+
+        let (data, truncated) = gateway.read_live_data().unwrap();
+        assert!(truncated);
+        let names: Vec<&str> = data.iter().flatten().map(SensorData::name).collect();
+        assert_eq!(names, ["indoor_temp"]);
+    }
+
+    #[test_log::test]
+    fn validates_captured_live_data() {
+        // Complete live data response with the payload of mock_gateway.json
+        let captured = [
+            0xFF, 0xFF, 0x27, 0x00, 0x24, 0x01, 0x00, 0xE6, 0x02, 0x00, 0xC8, 0x06, 0x37, 0x07, 0x32, 0x08, 0x27, 0x8F, 0x09,
+            0x27, 0xB5, 0x0A, 0x00, 0xB4, 0x0B, 0x00, 0x12, 0x0C, 0x00, 0x1E, 0x17, 0x03, 0x1A, 0x00, 0xDC, 0x22, 0x2D, 0x79,
+        ];
+
+        assert!(SensorGateway::validate_response(&captured, &[GatewayCommands::LiveData as u8]).is_ok());
+        assert_eq!(SensorGateway::generate_checksum(&captured[2..captured.len() - 1]), 0x79);
+        assert_eq!(SensorGateway::expected_frame_len(&captured), Some(captured.len()));
+    }
+
+    #[test_log::test]
+    fn command_packets_validate_when_echoed() {
+        let (gateway, _) = mock_gateway(&[]);
+
+        for (cmd, payload) in [(GatewayCommands::ReadFirmwareVersion, vec![]), (GatewayCommands::WriteRainData, vec![0x12; 16])] {
+            let packet = gateway.build_cmd_packet(&cmd, &payload);
+            assert_eq!(packet[3] as usize, payload.len() + 3);
+            assert!(SensorGateway::validate_response(&packet, &[cmd as u8]).is_ok(), "{:?}", packet);
+            assert_eq!(SensorGateway::expected_frame_len(&packet), Some(packet.len()));
+        }
+    }
+
+    #[test_log::test]
+    fn decodes_rain_gain() {
+        // Gain of 1.00 next to the outdoor temp
+        let data = [0x0F, 0x00, 0x64, 0x02, 0x00, 0xC8];
+
+        let sensors = Sensors::new().parse_live_data(&data).unwrap();
+        let values: Vec<(&str, &SensorValue)> = sensors.iter().flatten().map(|sensor| (sensor.name(), sensor.value())).collect();
+        assert!(matches!(values[..], [("rain_gain", SensorValue::Gain(1.0)), ("outdoor_temp", _)]));
+        assert!(SensorValue::parse_gain(&[0x00, 0x00, 0x00, 0x64]).is_err());
+    }
+
+    #[test_log::test]
+    fn keeps_sensors_sharing_an_address() {
+        // WH65 and WH31 channel 1 both reporting address 0xC4
+        let payload = [0x00, 0x00, 0x00, 0x00, 0xC4, 0x00, 0x04, 0x06, 0x00, 0x00, 0x00, 0xC4, 0x00, 0x03];
+        let sensors = Sensors::new();
+
+        for _ in 0..2 {
+            let metadata = sensors.update_metadata(&MockTransport::frame(GatewayCommands::ReadSensorIdNew as u8, &payload)).unwrap();
+            assert_eq!(metadata.len(), 2);
+            assert_eq!(metadata[&(0x00, 0xC4)].signal, 4);
+            assert_eq!(metadata[&(0x06, 0xC4)].signal, 3);
+        }
+        assert_eq!(sensors.duplicate_addresses.lock().unwrap().len(), 1);
+    }
+
+    #[test_log::test]
+    fn typed_accessors_round_values() {
+        assert_eq!(SensorValue::Temp(21.456).as_f64(), Some(21.46));
+        assert_eq!(SensorValue::Humidity(55.004).as_f64(), Some(55.0));
+        assert_eq!(SensorValue::Pressure(1013.255).as_f64(), Some(SensorValue::round(&1013.255, SensorValue::DEFAULT_PRECISION)));
+        assert_eq!(SensorValue::Rain(1.239).as_f64(), Some(1.24));
+        assert_eq!(SensorValue::Co2(450).as_f64(), Some(450.0));
+        assert_eq!(SensorValue::Direction(270).as_f64(), Some(270.0));
+        assert_eq!(SensorValue::Battery(SensorBatteryState::Low).as_f64(), None);
+        assert_eq!(SensorValue::Empty.as_f64(), None);
+
+        assert_eq!(SensorValue::Battery(SensorBatteryState::Low).as_battery(), Some(SensorBatteryState::Low));
+        assert_eq!(SensorValue::Temp(21.456).as_battery(), None);
+    }
+
+    #[test_log::test]
+    fn resolves_address_with_port() {
+        assert_eq!(SensorGateway::resolve_address("192.168.1.10", 45000).unwrap(), "192.168.1.10:45000".parse().unwrap());
+        assert_eq!(SensorGateway::resolve_address("192.168.1.10:47000", 45000).unwrap(), "192.168.1.10:47000".parse().unwrap());
+
+        let address = SensorGateway::resolve_address("localhost:47000", 45000).unwrap();
+        assert!(address.ip().is_loopback());
+        assert_eq!(address.port(), 47000);
+
+        assert!(SensorGateway::resolve_address("192.168.1.10:port", 45000).is_err());
+        assert!(SensorGateway::with_options("192.168.1.10:port".to_string(), 45000, ConnectionOptions::default()).is_err());
+    }
+
+    #[test_log::test]
+    fn resolves_hostnames_and_ipv6() {
+        let address = SensorGateway::resolve_address("localhost", 45000).unwrap();
+        assert!(address.ip().is_loopback());
+        assert_eq!(address.port(), 45000);
+
+        assert_eq!(SensorGateway::resolve_address("::1", 45000).unwrap(), "[::1]:45000".parse().unwrap());
+        assert_eq!(SensorGateway::resolve_address("[fe80::1]:47000", 45000).unwrap(), "[fe80::1]:47000".parse().unwrap());
+
+        assert!(SensorGateway::resolve_address("gateway.invalid", 45000).is_err());
+        assert!(SensorGateway::new("gateway.invalid".to_string(), 45000).is_err());
+    }
+
+    #[test_log::test]
+    fn spaces_commands_by_min_interval() {
+        let interval = Duration::from_millis(200);
+        let last = Instant::now();
+        assert_eq!(SensorGateway::command_delay(None, last, interval), Duration::ZERO);
+        assert_eq!(SensorGateway::command_delay(Some(last), last, interval), interval);
+        assert_eq!(SensorGateway::command_delay(Some(last), last + Duration::from_millis(150), interval), Duration::from_millis(50));
+        assert_eq!(SensorGateway::command_delay(Some(last), last + Duration::from_millis(300), interval), Duration::ZERO);
+
+        let transport = Arc::new(MockTransport::new());
+        transport.respond(GatewayCommands::ReadFirmwareVersion as u8, b"\x04V1.0");
+        let options = ConnectionOptions { min_command_interval: Duration::from_millis(50), ..ConnectionOptions::default() };
+        let gateway = SensorGateway::build("127.0.0.1:45000".parse().unwrap(), Box::new(transport.clone()), options, None);
+        let start = Instant::now();
+        gateway.get_firmware_version().unwrap();
+        gateway.get_firmware_version().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test_log::test]
+    fn flattens_wh45_fields() {
+        // WH45 then the outdoor temp
+        let live_data = [0x70, 0x00, 0xD7, 0x37, 0x00, 0x64, 0x00, 0x5A, 0x00, 0x32, 0x00, 0x28, 0x01, 0xC2, 0x01, 0xF4, 0x04, 0x02, 0x00, 0xC8];
+        let (gateway, _) = mock_gateway(&[(GatewayCommands::LiveData, &live_data)]);
+
+        let flat = gateway.get_live_data_flat().unwrap();
+        let names: Vec<&str> = flat.iter().map(SensorData::name).collect();
+        assert_eq!(names, ["temp_wh45", "humid_wh45", "pm10_wh45", "pm10_avg_24h_wh45", "pm25_wh45", "pm25_avg_24h_wh45",
+                           "co2_wh45", "co2_avg_24h_wh45", "battery_wh45", "outdoor_temp"]);
+        let values: Vec<Option<f64>> = flat.iter().map(|sensor| sensor.value().as_f64()).collect();
+        assert_eq!(values, [Some(21.5), Some(55.0), Some(10.0), Some(9.0), Some(5.0), Some(4.0), Some(450.0), Some(500.0), None, Some(20.0)]);
+
+        let reading = GatewayReading { time: SystemTime::now(), metadata: HashMap::new(), data: gateway.get_live_data().unwrap(), truncated: false };
+        assert!(reading.sensors().map(SensorData::name).eq(names));
+    }
+
+    #[test_log::test]
+    fn rounds_to_precision() {
+        let temp = SensorValue::Temp(21.4567);
+        assert_eq!(temp.to_json_val_with_precision(0), json!(21.0));
+        assert_eq!(temp.to_json_val_with_precision(1), json!(21.5));
+        assert_eq!(temp.to_json_val_with_precision(3), json!(21.457));
+        assert_eq!(SensorValue::to_json_val(&temp), json!(21.46));
+        assert_eq!(SensorValue::Co2(450).to_json_val_with_precision(0), json!(450));
+
+        // Rounded after converting to imperial units, 21.4567°C is 70.62206°F
+        assert_eq!(temp.to_json_val_with_units_precision(UnitSystem::Imperial, 1), json!(70.6));
+        assert_eq!(temp.to_json_val_with_units_precision(UnitSystem::Imperial, 3), json!(70.622));
+    }
+
+    #[test_log::test]
+    fn outdoor_fields_come_from_wh65() {
+        let meta = |type_id, address| ((type_id, address), SensorMetadata::new(type_id, address, Some(0.0), 4));
+        let mut metadata = HashMap::from([meta(0x05, 0x20), meta(0x00, 0x10), meta(0x03, 0x30)]);
+
+        let source = |field: &str, metadata: &HashMap<(u8, u32), SensorMetadata>| SensorMetadata::source_of(field, metadata).map(|meta| meta.type_id_str.clone());
+        assert_eq!(source("outdoor_temp", &metadata).as_deref(), Some("wh65"));
+        assert_eq!(source("wind_speed", &metadata).as_deref(), Some("wh65"));
+        assert_eq!(source("rain_day", &metadata).as_deref(), Some("wh65"));
+        assert_eq!(source("indoor_temp", &metadata), None);
+
+        // Without the array the single sensors report
+        metadata.remove(&(0x00, 0x10));
+        assert_eq!(source("outdoor_temp", &metadata).as_deref(), Some("wh26"));
+        assert_eq!(source("rain_day", &metadata).as_deref(), Some("wh40"));
+    }
+
+    #[test_log::test]
+    fn decodes_new_and_legacy_sensor_ids() {
+        // WH65 with battery 0 and signal 4, then an unpaired WH68
+        let records = [0x00, 0x00, 0x00, 0x00, 0xC4, 0x00, 0x04, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00];
+        let metadata = Sensors::new().update_metadata(&MockTransport::frame(GatewayCommands::ReadSensorIdNew as u8, &records)).unwrap();
+        assert_eq!(metadata.len(), 1);
+        assert_eq!((metadata[&(0x00, 0xC4)].type_id_str.as_str(), metadata[&(0x00, 0xC4)].signal), ("wh65", 4));
+
+        // The legacy records have no signal byte and a one byte size
+        let records = [0x00, 0x00, 0x00, 0x00, 0xC4, 0x00, 0x06, 0x00, 0x00, 0x00, 0xC5, 0x01];
+        let frame = MockTransport::frame(GatewayCommands::ReadSensorId as u8, &records);
+        assert_eq!(frame[3] as usize, records.len() + 3);
+        let metadata = Sensors::new().update_metadata_legacy(&frame).unwrap();
+        assert_eq!(metadata.len(), 2);
+        assert_eq!((metadata[&(0x06, 0xC5)].type_id_str.as_str(), metadata[&(0x06, 0xC5)].signal), ("wh31_ch1", 0));
+        assert!(Sensors::new().update_metadata_legacy(&frame[..frame.len() - 3]).is_err());
+    }
+
+    #[test_log::test]
+    fn falls_back_to_legacy_sensor_ids_on_invalid_response() {
+        let sent_commands = |transport: &MockTransport| transport.sent().iter().map(|packet| packet[2]).collect::<Vec<u8>>();
+        let legacy = [0x00, 0x00, 0x00, 0x00, 0xC4, 0x00];
+
+        // Unreachable, not taken for an old gateway
+        let (gateway, transport) = mock_gateway(&[(GatewayCommands::ReadSensorId, &legacy)]);
+        assert!(matches!(gateway.update_sensor_metadata(), Err(GatewayError::Io(_))));
+        assert!(!sent_commands(&transport).contains(&0x3A));
+        assert!(gateway.sensor_id_command.lock().unwrap().is_none());
+
+        // Answered with a corrupt frame, the legacy command is used from then on
+        let mut corrupt = MockTransport::frame(GatewayCommands::ReadSensorIdNew as u8, &[]);
+        *corrupt.last_mut().unwrap() ^= 0xFF;
+        transport.respond_raw(GatewayCommands::ReadSensorIdNew as u8, corrupt);
+        assert_eq!(gateway.update_sensor_metadata().unwrap().len(), 1);
+        assert!(matches!(*gateway.sensor_id_command.lock().unwrap(), Some(GatewayCommands::ReadSensorId)));
+
+        // Until the current command works, e.g. after a firmware update
+        transport.respond(GatewayCommands::ReadSensorIdNew as u8, &[0x06, 0x00, 0x00, 0x00, 0xC5, 0x01, 0x03]);
+        assert_eq!(gateway.update_sensor_metadata().unwrap().keys().collect::<Vec<_>>(), [&(0x06, 0xC5)]);
+        assert!(matches!(*gateway.sensor_id_command.lock().unwrap(), Some(GatewayCommands::ReadSensorIdNew)));
+    }
+
+    #[test_log::test]
+    fn lazy_gateway_reads_on_demand() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let gateway = SensorGateway::new_lazy("127.0.0.1".to_string(), port, ConnectionOptions::default()).unwrap();
+        assert_eq!(gateway.address(), listener.local_addr().unwrap());
+        assert_eq!(listener.accept().unwrap_err().kind(), std::io::ErrorKind::WouldBlock);
+        let invalid = ConnectionOptions { max_tries: 0, ..ConnectionOptions::default() };
+        assert!(SensorGateway::new_lazy("127.0.0.1".to_string(), port, invalid).is_err());
+
+        let transport = Arc::new(MockTransport::new());
+        transport.respond(GatewayCommands::ReadStationMac as u8, &[0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
+        transport.respond(GatewayCommands::ReadFirmwareVersion as u8, b"\x04V1.0");
+        let gateway = SensorGateway::with_transport_lazy("127.0.0.1:45000".parse().unwrap(), Box::new(transport.clone()));
+        assert!(transport.sent().is_empty());
+
+        assert!(gateway.name().contains("aabbccddeeff"));
+        assert_eq!(gateway.firmware(), "V1.0");
+        gateway.name();
+        let commands: Vec<u8> = transport.sent().iter().map(|packet| packet[2]).collect();
+        assert_eq!(commands, [GatewayCommands::ReadStationMac as u8, GatewayCommands::ReadFirmwareVersion as u8]);
+    }
+
+    #[test_log::test]
+    fn decodes_soil_calibration() {
+        // Channel 1 at 45% from AD 250 with default bounds, channel 2 at 60% from AD 180 with custom bounds 70 to 300
+        let calibration = [0x00, 45, 0x00, 0xFA, 0x00, 0x46, 0x01, 0x7C, 0x01, 60, 0x00, 0xB4, 0x01, 0x46, 0x01, 0x2C];
+        let (gateway, transport) = mock_gateway(&[(GatewayCommands::ReadSoilCalibration, &calibration)]);
+
+        let channels = gateway.get_soil_calibration().unwrap();
+        assert_eq!(channels.len(), 2);
+        let second = &channels[1];
+        assert_eq!((second.channel, second.humidity, second.ad, second.custom, second.min_ad, second.max_ad), (1, 60, 180, true, 70, 300));
+
+        let raw = gateway.get_soil_raw().unwrap();
+        let values: Vec<(u8, u16, u16, u16, u8)> = raw.iter().map(|raw| (raw.channel, raw.ad, raw.min_ad, raw.max_ad, raw.humidity)).collect();
+        assert_eq!(values, [(0, 250, 70, 380, 45), (1, 180, 70, 300, 60)]);
+
+        transport.respond(GatewayCommands::ReadSoilCalibration as u8, &calibration[..12]);
+        assert!(matches!(gateway.get_soil_calibration(), Err(GatewayError::Parse(_))));
     }
 }
@@ -0,0 +1,96 @@
+use std::{sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}, time::{Duration, Instant}, fmt::Write};
+use tiny_http::{Server, Response, Header};
+
+// Counters for a single gateway, updated from the poll loop and read by the http server
+#[derive(Debug, Default)]
+pub struct GatewayMetrics {
+    polls_ok: AtomicU64,
+    polls_failed: AtomicU64,
+    discovery_sent: AtomicU64,
+    publish_failures: AtomicU64,
+    // Times the gateway was reported offline after too many failed polls
+    marked_offline: AtomicU64,
+    last_poll_ok: Mutex<Option<Instant>>,
+}
+
+impl GatewayMetrics {
+    pub fn poll_succeeded(&self) {
+        self.polls_ok.fetch_add(1, Ordering::Relaxed);
+        *self.last_poll_ok.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn poll_failed(&self) {
+        self.polls_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn marked_offline(&self) {
+        self.marked_offline.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn discovery_sent(&self) {
+        self.discovery_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn publish_failed(&self) {
+        self.publish_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn healthy(&self, max_age: Duration) -> bool {
+        self.last_poll_ok.lock().unwrap().is_some_and(|last| last.elapsed() <= max_age)
+    }
+}
+
+fn write_counter(out: &mut String, name: &str, help: &str, gateways: &[(String, Arc<GatewayMetrics>)], counter: fn(&GatewayMetrics) -> &AtomicU64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for (gateway, metrics) in gateways {
+        let _ = writeln!(out, "{}{{gateway=\"{}\"}} {}", name, gateway, counter(metrics).load(Ordering::Relaxed));
+    }
+}
+
+fn render_metrics(gateways: &[(String, Arc<GatewayMetrics>)]) -> String {
+    let mut out = String::new();
+    write_counter(&mut out, "awgateway_polls_succeeded_total", "Successful live data polls", gateways, |m| &m.polls_ok);
+    write_counter(&mut out, "awgateway_polls_failed_total", "Failed live data polls", gateways, |m| &m.polls_failed);
+    write_counter(&mut out, "awgateway_marked_offline_total", "Times the gateway was reported offline after failure_threshold failed polls", gateways, |m| &m.marked_offline);
+    write_counter(&mut out, "awgateway_discovery_sent_total", "Home Assistant discovery messages sent", gateways, |m| &m.discovery_sent);
+    write_counter(&mut out, "awgateway_mqtt_publish_failures_total", "Failed mqtt publishes", gateways, |m| &m.publish_failures);
+    out
+}
+
+// Serve /healthz, /metrics and /version (the `version` json) on a background thread. `poll_interval_sec`
+// follows reloads of the settings
+pub fn spawn_server(port: u16, poll_interval_sec: Arc<AtomicU64>, gateways: Vec<(String, Arc<GatewayMetrics>)>, version: String) -> Result<(), String> {
+    let server = Server::http(("0.0.0.0", port)).map_err(|e| format!("Failed to start http server on port {} - error {:?}", port, e))?;
+    log::info!("Serving health and metrics on port {}", port);
+
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = match request.url() {
+                "/healthz" => {
+                    let max_age = Duration::from_secs(poll_interval_sec.load(Ordering::Relaxed) * 2);
+                    if gateways.iter().all(|(_, metrics)| metrics.healthy(max_age)) {
+                        Response::from_string("ok")
+                    } else {
+                        Response::from_string("unhealthy").with_status_code(503)
+                    }
+                }
+                "/metrics" => {
+                    let content_type = Header::from_bytes("Content-Type", "text/plain; version=0.0.4").unwrap();
+                    Response::from_string(render_metrics(&gateways)).with_header(content_type)
+                }
+                "/version" => {
+                    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+                    Response::from_string(version.clone()).with_header(content_type)
+                }
+                _ => Response::from_string("not found").with_status_code(404),
+            };
+
+            if let Err(e) = request.respond(response) {
+                log::debug!("Failed to respond to http request - error {:?}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,259 @@
+//
+// Transport over the local http json api of newer gateways (GW1100, GW2000, WH2650), for firmware
+// with an unreliable command port. The json is turned into the frames the command port would send,
+// so SensorGateway decodes them like those of TcpTransport
+//
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::gateway::GatewayCommands;
+use crate::transport::{self, Transport};
+
+// Port of the gateway's web interface and api
+pub const HTTP_PORT: u16 = 80;
+
+// The live data json of a station with many sensors is a few kB
+const MAX_RESPONSE: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Quantity {
+    Temp,
+    Humidity,
+    Pressure,
+    Direction,
+    Speed,
+    Rain,
+    Light,
+    UvIndex,
+}
+
+#[derive(Debug)]
+pub struct HttpTransport {
+    address: SocketAddr,
+    timeout: Duration,
+}
+
+impl HttpTransport {
+    pub fn new(address: SocketAddr, timeout: Duration) -> Self {
+        HttpTransport { address, timeout }
+    }
+
+    // Json body of a GET request. HTTP/1.0 keeps the gateways from answering with chunked encoding
+    fn get(&self, path: &str) -> Result<Value, Error> {
+        log::trace!("Requesting http://{}{}", self.address, path);
+        let mut s = TcpStream::connect_timeout(&self.address, self.timeout)?;
+        s.set_read_timeout(Some(self.timeout))?;
+        s.set_write_timeout(Some(self.timeout))?;
+        let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, self.address.ip());
+        s.write_all(request.as_bytes())?;
+
+        let mut response = Vec::new();
+        s.take(MAX_RESPONSE).read_to_end(&mut response)?;
+        let header_end = response.windows(4).position(|window| window == b"\r\n\r\n")
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("Incomplete http response to {} from {:?}", path, self.address)))?;
+
+        let header = String::from_utf8_lossy(&response[..header_end]);
+        let status = header.lines().next().unwrap_or_default();
+        if status.split_whitespace().nth(1) != Some("200") {
+            return Err(Error::other(format!("Request for {} to {:?} failed - {}", path, self.address, status)));
+        }
+
+        serde_json::from_slice(&response[header_end + 4..])
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("Invalid json in response to {} from {:?} - {}", path, self.address, e)))
+    }
+
+    // "Version: GW1100A_V2.1.4" from /get_version, sent like the 0x50 response with a size byte
+    fn firmware_payload(version: &Value) -> Option<Vec<u8>> {
+        let version = version["version"].as_str()?;
+        let version = version.strip_prefix("Version:").unwrap_or(version).trim();
+        let mut payload = vec![u8::try_from(version.len()).ok()?];
+        payload.extend_from_slice(version.as_bytes());
+        Some(payload)
+    }
+
+    // "mac" of /get_network_info like "AA:BB:CC:DD:EE:FF"
+    fn mac_payload(network: &Value) -> Option<Vec<u8>> {
+        let mac = network["mac"].as_str()?;
+        let bytes = mac.split([':', '-']).map(|byte| u8::from_str_radix(byte, 16).ok()).collect::<Option<Vec<u8>>>()?;
+        Some(bytes).filter(|bytes| bytes.len() == 6)
+    }
+
+    // Type id, quantity, size and scale of the binary encoding of the fields the api reports,
+    // matching the parsers of Sensors
+    fn binary_field(type_id: u8) -> Option<(Quantity, usize, f64)> {
+        match type_id {
+            0x01 | 0x02 | 0x1A..=0x21 => Some((Quantity::Temp, 2, 10.0)),
+            0x06 | 0x07 | 0x22..=0x29 => Some((Quantity::Humidity, 1, 1.0)),
+            0x2C | 0x2E | 0x30 | 0x32 | 0x34 | 0x36 | 0x38 | 0x3A => Some((Quantity::Humidity, 1, 1.0)),
+            0x08 | 0x09 => Some((Quantity::Pressure, 2, 10.0)),
+            0x0A => Some((Quantity::Direction, 2, 1.0)),
+            0x0B | 0x0C | 0x19 => Some((Quantity::Speed, 2, 10.0)),
+            0x0D | 0x0E | 0x10 | 0x11 | 0x80 | 0x81 => Some((Quantity::Rain, 2, 10.0)),
+            0x12 | 0x13 | 0x14 | 0x83..=0x86 => Some((Quantity::Rain, 4, 10.0)),
+            0x15 => Some((Quantity::Light, 4, 100.0)),
+            0x17 => Some((Quantity::UvIndex, 1, 1.0)),
+            _ => None,
+        }
+    }
+
+    // Value in the metric units of the binary protocol, the api uses the units set in the gateway
+    fn metric(quantity: Quantity, value: f64, unit: &str) -> Option<f64> {
+        let unit = unit.trim().to_lowercase();
+        let factor = match (quantity, unit.trim_end_matches("/hr")) {
+            (Quantity::Temp, "f" | "℉") => return Some((value - 32.0) * 5.0 / 9.0),
+            (Quantity::Temp, "" | "c" | "℃") => 1.0,
+            (Quantity::Humidity, "" | "%") => 1.0,
+            (Quantity::Direction, "" | "°" | "º") => 1.0,
+            (Quantity::UvIndex, "") => 1.0,
+            (Quantity::Pressure, "hpa") => 1.0,
+            (Quantity::Pressure, "inhg") => 33.8639,
+            (Quantity::Pressure, "mmhg") => 1.33322,
+            (Quantity::Speed, "m/s") => 1.0,
+            (Quantity::Speed, "km/h") => 1.0 / 3.6,
+            (Quantity::Speed, "mph") => 0.44704,
+            (Quantity::Speed, "knots") => 0.514444,
+            (Quantity::Speed, "ft/s") => 0.3048,
+            (Quantity::Rain, "mm") => 1.0,
+            (Quantity::Rain, "in") => 25.4,
+            (Quantity::Light, "lux") => 1.0,
+            (Quantity::Light, "klux") => 1000.0,
+            (Quantity::Light, "w/m2") => 126.7,
+            (Quantity::Light, "fc") => 10.764,
+            _ => return None,
+        };
+        Some(value * factor)
+    }
+
+    // "21.4", "77%" or "0.00 m/s" with the unit from `unit` or after the number, None for "--"
+    fn field(type_id: u8, value: &Value, unit: Option<&Value>) -> Option<Vec<u8>> {
+        let (quantity, size, scale) = Self::binary_field(type_id)?;
+        let value = value.as_str()?.trim();
+        let number_end = value.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-')).unwrap_or(value.len());
+        let number: f64 = value[..number_end].parse().ok()?;
+        let unit = unit.and_then(Value::as_str).unwrap_or(&value[number_end..]);
+        let Some(metric) = Self::metric(quantity, number, unit) else {
+            log::debug!("Skipping http live data field {:#04x} with unknown unit {:?}", type_id, unit);
+            return None;
+        };
+
+        let raw = (metric * scale).round() as i64;
+        let mut field = vec![type_id];
+        match size {
+            1 => field.push(raw as u8),
+            2 => field.extend_from_slice(&(raw as i16).to_be_bytes()),
+            _ => field.extend_from_slice(&(raw as u32).to_be_bytes()),
+        }
+        Some(field)
+    }
+
+    fn hex_id(id: &Value) -> Option<u8> {
+        u8::from_str_radix(id.as_str()?.strip_prefix("0x")?, 16).ok()
+    }
+
+    // Payload of a 0x27 live data frame from /get_livedata_info. Lightning, PM2.5, leak, WH45 and
+    // the other fields without a mapping are left out
+    pub fn livedata_payload(livedata: &Value) -> Vec<u8> {
+        let list = |key: &str| livedata[key].as_array().cloned().unwrap_or_default();
+        let mut fields: Vec<Vec<u8>> = Vec::new();
+
+        for item in list("common_list").iter().chain(list("rain").iter()) {
+            fields.extend(Self::hex_id(&item["id"]).and_then(|id| Self::field(id, &item["val"], item.get("unit"))));
+        }
+
+        // Same ids as the tipping bucket, the binary protocol has its own
+        for item in list("piezoRain") {
+            let id = Self::hex_id(&item["id"]).and_then(|id| match id {
+                0x0E => Some(0x80),
+                0x0D => Some(0x81),
+                0x10 => Some(0x83),
+                0x11 => Some(0x84),
+                0x12 => Some(0x85),
+                0x13 => Some(0x86),
+                _ => None,
+            });
+            fields.extend(id.and_then(|id| Self::field(id, &item["val"], item.get("unit"))));
+        }
+
+        for item in list("wh25") {
+            fields.extend(Self::field(0x01, &item["intemp"], item.get("unit")));
+            fields.extend(Self::field(0x06, &item["inhumi"], None));
+            fields.extend(Self::field(0x08, &item["abs"], None));
+            fields.extend(Self::field(0x09, &item["rel"], None));
+        }
+
+        let channels = |key: &str| list(key).into_iter()
+            .filter_map(|item| Some((item["channel"].as_str()?.parse::<u8>().ok().filter(|channel| (1..=8).contains(channel))?, item)));
+        for (channel, item) in channels("ch_aisle") {
+            fields.extend(Self::field(0x19 + channel, &item["temp"], item.get("unit")));
+            fields.extend(Self::field(0x21 + channel, &item["humidity"], None));
+        }
+        for (channel, item) in channels("ch_soil") {
+            fields.extend(Self::field(0x2A + 2 * channel, &item["humidity"], None));
+        }
+
+        fields.concat()
+    }
+}
+
+impl Transport for HttpTransport {
+    // Answers the commands the api has data for, others fail with ErrorKind::Unsupported
+    fn send_and_receive(&self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        let cmd = *packet.get(2).ok_or(Error::new(ErrorKind::InvalidInput, "Command packet without command"))?;
+        let invalid = |what: &str| Error::new(ErrorKind::InvalidData, format!("No {} in the http api of {:?}", what, self.address));
+
+        let payload = match cmd {
+            c if c == GatewayCommands::LiveData as u8 => Self::livedata_payload(&self.get("/get_livedata_info")?),
+            c if c == GatewayCommands::ReadFirmwareVersion as u8 => Self::firmware_payload(&self.get("/get_version")?).ok_or(invalid("firmware version"))?,
+            c if c == GatewayCommands::ReadStationMac as u8 => Self::mac_payload(&self.get("/get_network_info")?).ok_or(invalid("MAC address"))?,
+            // Sensor ids aren't in the api, an empty list keeps polls working without metadata
+            c if c == GatewayCommands::ReadSensorIdNew as u8 => Vec::new(),
+            _ => return Err(Error::new(ErrorKind::Unsupported, format!("Command {:#04x} is not available over http", cmd))),
+        };
+        Ok(transport::frame(cmd, &payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway::Sensors;
+
+    #[test_log::test]
+    fn maps_imperial_live_data_to_sensor_data() {
+        // /get_livedata_info of a gateway set to °F, inHg, mph and in
+        let livedata: Value = serde_json::from_str(r#"{
+            "common_list": [
+                {"id": "0x02", "val": "68.5", "unit": "F"},
+                {"id": "0x07", "val": "55%"},
+                {"id": "0x03", "val": "50.1", "unit": "F"},
+                {"id": "0x0B", "val": "5.37 mph"},
+                {"id": "0x0C", "val": "8.05 mph"},
+                {"id": "0x19", "val": "11.41 mph"},
+                {"id": "0x15", "val": "215.32 W/m2"},
+                {"id": "0x17", "val": "2"},
+                {"id": "0x0A", "val": "270"}
+            ],
+            "rain": [
+                {"id": "0x0D", "val": "0.12 in"},
+                {"id": "0x0E", "val": "0.02 in/Hr"},
+                {"id": "0x10", "val": "0.20 in"},
+                {"id": "0x13", "val": "10.50 in", "battery": "0"}
+            ],
+            "wh25": [{"intemp": "72.1", "unit": "F", "inhumi": "45%", "abs": "29.68 inHg", "rel": "29.92 inHg"}],
+            "ch_aisle": [{"channel": "1", "name": "", "battery": "0", "temp": "70.2", "unit": "F", "humidity": "50%"}]
+        }"#).unwrap();
+
+        let data = Sensors::new().parse_live_data(&HttpTransport::livedata_payload(&livedata)).unwrap();
+        let values: Vec<(&str, Option<f64>)> = data.iter().flatten().map(|sensor| (sensor.name(), sensor.value().as_f64())).collect();
+        assert_eq!(values, [
+            ("outdoor_temp", Some(20.3)), ("out_humidity", Some(55.0)), ("wind_speed", Some(2.4)), ("gust_speed", Some(3.6)),
+            ("day_maxwind", Some(5.1)), ("light", Some(27281.04)), ("uv_index", Some(2.0)), ("wind_dir", Some(270.0)),
+            ("rain_event", Some(3.0)), ("rain_rate", Some(0.5)), ("rain_day", Some(5.1)), ("rain_year", Some(266.7)),
+            ("indoor_temp", Some(22.3)), ("in_humidity", Some(45.0)), ("abs_barometer", Some(1005.1)), ("rel_barometer", Some(1013.2)),
+            ("temp_ch1", Some(21.2)), ("humidity_1", Some(50.0)),
+        ]);
+    }
+}
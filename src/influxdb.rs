@@ -0,0 +1,132 @@
+use std::time::UNIX_EPOCH;
+use aw_gateway_rs::gateway::{GatewayReading, UnitSystem};
+use serde_json::Value;
+
+use crate::Output;
+
+const MEASUREMENT: &str = "ecowitt";
+
+#[derive(Debug, Clone)]
+pub struct InfluxDbConfig {
+    url: String,
+    token: String,
+    org: String,
+    bucket: String,
+}
+
+impl InfluxDbConfig {
+    pub fn parse(config: &config::Config) -> Result<Self, String> {
+        let get = |key: &str| config.get_string(&format!("influxdb.{}", key)).map_err(|_| format!("Missing influxdb.{} config", key));
+
+        Ok(InfluxDbConfig {
+            url: get("url")?.trim_end_matches('/').to_string(),
+            token: get("token")?,
+            org: get("org")?,
+            bucket: get("bucket")?,
+        })
+    }
+}
+
+// Writes each reading as one line protocol point to the InfluxDB v2 write api
+pub struct InfluxDbOutput {
+    gateway_name: String,
+    config: InfluxDbConfig,
+    units: UnitSystem,
+    dry_run: bool,
+    agent: ureq::Agent,
+}
+
+// Tag keys, tag values and field keys need commas, equal signs and spaces escaped
+fn escape_key(key: &str) -> String {
+    key.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+fn field_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Number(num) if num.is_f64() => Some(num.to_string()),
+        Value::Number(num) => Some(format!("{}i", num)),
+        Value::String(s) => Some(format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+pub fn to_line_protocol(gateway_name: &str, reading: &GatewayReading, units: UnitSystem) -> Option<String> {
+    let fields: Vec<String> = reading.sensors()
+        .filter_map(|sensor| {
+            let value = field_value(&sensor.value().to_json_val_with_units(units))?;
+            Some(format!("{}={}", escape_key(sensor.name()), value))
+        })
+        .collect();
+
+    if fields.is_empty() {
+        return None;
+    }
+
+    let timestamp = reading.time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    Some(format!("{},gateway={} {} {}", MEASUREMENT, escape_key(gateway_name), fields.join(","), timestamp))
+}
+
+impl InfluxDbOutput {
+    pub fn new(gateway_name: String, config: InfluxDbConfig, units: UnitSystem, dry_run: bool) -> Self {
+        InfluxDbOutput {
+            gateway_name,
+            config,
+            units,
+            dry_run,
+            agent: ureq::Agent::new(),
+        }
+    }
+}
+
+impl Output for InfluxDbOutput {
+    fn publish(&self, reading: &GatewayReading) -> Result<(), String> {
+        let Some(line) = to_line_protocol(&self.gateway_name, reading, self.units) else {
+            log::info!("No fields to write for {}", self.gateway_name);
+            return Ok(());
+        };
+
+        if self.dry_run {
+            log::info!("Dry run, not writing to {}: {}", self.config.url, line);
+            return Ok(());
+        }
+
+        log::debug!("Writing {:?} to influxdb", line);
+        self.agent.post(&format!("{}/api/v2/write", self.config.url))
+            .query("org", &self.config.org)
+            .query("bucket", &self.config.bucket)
+            .query("precision", "ns")
+            .set("Authorization", &format!("Token {}", self.config.token))
+            .set("Content-Type", "text/plain; charset=utf-8")
+            .send_string(&line)
+            .map_err(|e| format!("Failed to write to influxdb - error {:?}", e))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use aw_gateway_rs::gateway::{SensorBatteryState, SensorData, SensorValue};
+
+    #[test_log::test]
+    fn escapes_field_names() {
+        let reading = GatewayReading {
+            time: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            metadata: HashMap::new(),
+            data: vec![vec![
+                SensorData::new("outdoor temp", SensorValue::Temp(21.5)),
+                SensorData::new("a,b=c", SensorValue::Co2(450)),
+                SensorData::new("battery_wh45", SensorValue::Battery(SensorBatteryState::Ok)),
+                SensorData::new("", SensorValue::Empty),
+            ]],
+            truncated: false,
+        };
+
+        assert_eq!(to_line_protocol("gw 1", &reading, UnitSystem::Metric).unwrap(),
+                   "ecowitt,gateway=gw\\ 1 outdoor\\ temp=21.5,a\\,b\\=c=450i,battery_wh45=\"ok\" 1700000000000000000");
+    }
+}
@@ -0,0 +1,5 @@
+pub mod capture;
+pub mod derived;
+pub mod gateway;
+pub mod http;
+pub mod transport;
@@ -0,0 +1,89 @@
+//
+// Tracing spans in the flexi_logger output. Log records written inside a span, like one poll of a
+// gateway, are prefixed with the names and fields of the spans they're in
+//
+use std::fmt::{Debug, Write as _};
+use flexi_logger::DeferredNow;
+use log::Record;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record as SpanRecord};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Registry};
+
+// Fields of a span as ` key=value` pairs, filled in when the span is created and on record()
+struct SpanFields(String);
+
+struct FieldWriter<'a>(&'a mut String);
+
+impl Visit for FieldWriter<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let _ = write!(self.0, " {}={}", field.name(), value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        let _ = write!(self.0, " {}={:?}", field.name(), value);
+    }
+}
+
+struct SpanFieldsLayer;
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanFieldsLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = String::new();
+        attrs.record(&mut FieldWriter(&mut fields));
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &SpanRecord<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>() {
+                values.record(&mut FieldWriter(&mut fields.0));
+            }
+        }
+    }
+}
+
+// Subscriber keeping the fields of each span for span_context
+pub fn subscriber() -> impl Subscriber + Send + Sync {
+    Registry::default().with(SpanFieldsLayer)
+}
+
+// Track spans for span_context, before the logger is started
+pub fn init() -> Result<(), String> {
+    tracing::subscriber::set_global_default(subscriber())
+        .map_err(|err| format!("Failed to set up tracing - {}", err))
+}
+
+// Spans of the current thread from the outermost, like `poll{gateway=x poll_id=3}:update_livedata`
+fn span_context() -> Option<String> {
+    tracing::dispatcher::get_default(|dispatch| {
+        let registry = dispatch.downcast_ref::<Registry>()?;
+        let id = dispatch.current_span().id()?.clone();
+        let scope = registry.span(&id)?.scope().from_root().map(|span| {
+            let extensions = span.extensions();
+            match extensions.get::<SpanFields>().map(|fields| fields.0.trim_start()).filter(|fields| !fields.is_empty()) {
+                Some(fields) => format!("{}{{{}}}", span.name(), fields),
+                None => span.name().to_string(),
+            }
+        });
+        Some(scope.collect::<Vec<_>>().join(":"))
+    })
+}
+
+// flexi_logger::opt_format with the span context in front of the message
+pub fn format(w: &mut dyn std::io::Write, now: &mut DeferredNow, record: &Record) -> Result<(), std::io::Error> {
+    let context = span_context().map(|context| format!("{}: ", context)).unwrap_or_default();
+    write!(
+        w,
+        "[{}] {} [{}:{}] {}{}",
+        now.format(flexi_logger::TS_DASHES_BLANK_COLONS_DOT_BLANK),
+        record.level(),
+        record.file().unwrap_or("<unnamed>"),
+        record.line().unwrap_or(0),
+        context,
+        &record.args()
+    )
+}
@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+use aw_gateway_rs::gateway::{DisplayTimeZone, GatewayReading, SensorValue, UnitSystem};
+use serde_json::{json, Map, Value};
+
+use crate::json_value;
+
+// Latest reading of a gateway mirrored to a json file, for local scripts
+#[derive(Debug)]
+pub struct StateFile {
+    path: PathBuf,
+}
+
+impl StateFile {
+    // `path` itself with a single gateway, `<path>/<gateway name>.json` with several
+    pub fn new(path: &Path, gateway_name: &str, multiple: bool) -> Result<Self, String> {
+        let path = if multiple { path.join(format!("{}.json", gateway_name)) } else { path.to_path_buf() };
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|err| format!("Failed to create state file directory {:?} - {}", dir, err))?;
+        }
+        Ok(StateFile { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Written to a temporary file and renamed so readers never see a partial file
+    pub fn write(&self, gateway_name: &str, reading: &GatewayReading, units: UnitSystem, timezone: DisplayTimeZone) -> Result<(), String> {
+        let json = to_json(gateway_name, reading, units, timezone);
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+
+        std::fs::write(&tmp, json.to_string()).map_err(|err| format!("Failed to write {:?} - {}", tmp, err))?;
+        std::fs::rename(&tmp, &self.path).map_err(|err| format!("Failed to rename {:?} to {:?} - {}", tmp, self.path, err))
+    }
+}
+
+// All fields of the reading by name, with the time of the reading and the gateway name
+pub fn to_json(gateway_name: &str, reading: &GatewayReading, units: UnitSystem, timezone: DisplayTimeZone) -> Value {
+    let mut values: Map<String, Value> = reading.sensors()
+        .map(|sensor| (sensor.name().to_string(), json_value(sensor.value(), units, timezone, SensorValue::DEFAULT_PRECISION)))
+        .collect();
+    values.insert("timestamp".to_string(), json!(timezone.format(reading.time)));
+    values.insert("gateway".to_string(), json!(gateway_name));
+    Value::Object(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::time::{Duration, UNIX_EPOCH};
+    use aw_gateway_rs::gateway::SensorData;
+
+    #[test_log::test]
+    fn written_file_parses_back() {
+        let dir = std::env::temp_dir().join(format!("aw-gateway-rs-{}-state", std::process::id()));
+        let state_file = StateFile::new(&dir, "gw1", true).unwrap();
+        let reading = GatewayReading {
+            time: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            metadata: HashMap::new(),
+            data: vec![vec![SensorData::new("outdoor_temp", SensorValue::Temp(21.456))], vec![SensorData::new("co2", SensorValue::Co2(450))]],
+            truncated: false,
+        };
+
+        state_file.write("gw1", &reading, UnitSystem::Metric, DisplayTimeZone::Utc).unwrap();
+
+        assert_eq!(state_file.path(), dir.join("gw1.json"));
+        let written: Value = serde_json::from_str(&std::fs::read_to_string(state_file.path()).unwrap()).unwrap();
+        assert_eq!(written, json!({
+            "outdoor_temp": 21.46,
+            "co2": 450,
+            "timestamp": "2023-11-14T22:13:20+00:00",
+            "gateway": "gw1",
+        }));
+        assert_eq!(written, to_json("gw1", &reading, UnitSystem::Metric, DisplayTimeZone::Utc));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
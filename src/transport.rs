@@ -0,0 +1,349 @@
+//
+// Transports used by SensorGateway to exchange command/response frames with a gateway
+//
+use std::fmt::Debug;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[cfg(any(test, feature = "mock"))]
+use std::collections::HashMap;
+
+use crate::gateway::SensorGateway;
+
+pub trait Transport: Debug + Send + Sync {
+    // Send a complete command packet and return the complete response frame
+    fn send_and_receive(&self, packet: &[u8]) -> Result<Vec<u8>, Error>;
+
+    // Commands sent between begin_session and end_session may share a connection
+    fn begin_session(&self) {}
+    fn end_session(&self) {}
+}
+
+// Allows keeping a handle to a transport shared with a SensorGateway
+impl<T: Transport + ?Sized> Transport for std::sync::Arc<T> {
+    fn send_and_receive(&self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        (**self).send_and_receive(packet)
+    }
+
+    fn begin_session(&self) {
+        (**self).begin_session()
+    }
+
+    fn end_session(&self) {
+        (**self).end_session()
+    }
+}
+
+// Error source of a response that doesn't fit max_response_bytes, the same response would come back
+// on a retry
+#[derive(Debug)]
+pub struct ResponseTooLarge {
+    pub declared: Option<usize>,
+    pub max_response_bytes: usize,
+}
+
+impl std::fmt::Display for ResponseTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.declared {
+            Some(declared) => write!(f, "Response of {} bytes is larger than max_response_bytes {}", declared, self.max_response_bytes),
+            None => write!(f, "Response without a valid header is larger than max_response_bytes {}", self.max_response_bytes),
+        }
+    }
+}
+
+impl std::error::Error for ResponseTooLarge {}
+
+#[derive(Debug)]
+pub struct TcpTransport {
+    address: Mutex<SocketAddr>,
+    // Host and default port the address was resolved from, resolved again when connecting fails
+    host: Option<(String, u16)>,
+    timeout: Duration,
+    // Keep the connection open for the commands of a session
+    reuse: bool,
+    // Connection kept open while a session is active, None outside a session
+    session: Mutex<Option<Option<TcpStream>>>,
+    // Responses are cut off at this size
+    max_response: usize,
+}
+
+impl TcpTransport {
+    pub fn new(address: SocketAddr, timeout: Duration) -> Self {
+        TcpTransport { address: Mutex::new(address), host: None, timeout, reuse: false, session: Mutex::new(None), max_response: 4096 }
+    }
+
+    pub fn resolve_host(mut self, host: &str, port: u16) -> Self {
+        self.host = Some((host.to_string(), port));
+        self
+    }
+
+    fn address(&self) -> SocketAddr {
+        *self.address.lock().unwrap()
+    }
+
+    pub fn reuse_connection(mut self, reuse: bool) -> Self {
+        self.reuse = reuse;
+        self
+    }
+
+    pub fn max_response_bytes(mut self, max: usize) -> Self {
+        self.max_response = max;
+        self
+    }
+
+    fn connect(&self) -> Result<TcpStream, Error> {
+        let address = self.address();
+        log::trace!("Connecting to {:?}", address);
+        let s: TcpStream = match TcpStream::connect_timeout(&address, self.timeout) {
+            Ok(s) => s,
+            Err(error) => {
+                // The name may point somewhere else by now, e.g. after a new DHCP lease
+                let Some((host, resolved)) = self.host.as_ref()
+                    .and_then(|(host, port)| Some((host, SensorGateway::resolve_address(host, *port).ok()?)))
+                    .filter(|(_, resolved)| *resolved != address) else {
+                    return Err(error);
+                };
+                log::info!("Gateway {} now resolves to {:?} instead of {:?}", host, resolved, address);
+                *self.address.lock().unwrap() = resolved;
+                TcpStream::connect_timeout(&resolved, self.timeout)?
+            }
+        };
+
+        s.set_read_timeout(Some(self.timeout))?;
+        s.set_write_timeout(Some(self.timeout))?;
+        Ok(s)
+    }
+
+    fn close(&self, s: TcpStream) {
+        let res = s.shutdown(std::net::Shutdown::Both);
+        if res.is_err() {
+            log::error!("Failed to shutdown connection to {:?}", self.address());
+        }
+    }
+
+    fn exchange(&self, s: &mut TcpStream, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        log::trace!("Sending packet {:?} to {:?}", packet, self.address());
+
+        // Send the packet.
+        s.write_all(packet)?;
+
+        let mut rx_bytes = [0u8; 1024];
+        let mut vec = Vec::new();
+        let mut expected_len = None;
+
+        // Keep reading until the full frame declared in the header has arrived
+        while expected_len.is_none_or(|len| vec.len() < len) {
+            if vec.len() >= self.max_response {
+                // A cut off frame would only fail validation with a misleading checksum error
+                return Err(Error::new(ErrorKind::InvalidData, ResponseTooLarge { declared: expected_len, max_response_bytes: self.max_response }));
+            }
+
+            let n = match s.read(&mut rx_bytes) {
+                Ok(n) => n,
+                Err(error) => {
+                    log::error!("Failed to receive packet from {:?} - error {:?}, original packet {:?}", self.address(), error, packet);
+                    return Err(error);
+                }
+            };
+
+            if n == 0 {
+                return Err(Error::new(ErrorKind::UnexpectedEof, format!("Connection to {:?} closed after {} of {:?} bytes", 
+                                      self.address(), vec.len(), expected_len)));
+            }
+
+            vec.extend_from_slice(&rx_bytes[0..n.min(self.max_response - vec.len())]);
+            log::trace!("Received {:?} bytes (total {:?}) from {:?}", n, vec.len(), self.address());
+
+            if expected_len.is_none() {
+                expected_len = SensorGateway::expected_frame_len(&vec);
+            }
+        }
+
+        log::trace!("Received packet {:?} of size {:?} from {:?}", vec, vec.len(), self.address());
+
+        Ok(vec)
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send_and_receive(&self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut session = self.session.lock().unwrap();
+        let Some(cached) = session.as_mut() else {
+            let mut s = self.connect()?;
+            let res = self.exchange(&mut s, packet);
+            self.close(s);
+            return res;
+        };
+
+        if let Some(s) = cached.as_mut() {
+            match self.exchange(s, packet) {
+                Ok(vec) => return Ok(vec),
+                // The rest of the response is still unread, a new connection would get it again
+                Err(error) if error.kind() == ErrorKind::InvalidData => {
+                    if let Some(s) = cached.take() {
+                        self.close(s);
+                    }
+                    return Err(error);
+                }
+                // The gateway may have dropped the idle connection
+                Err(error) => {
+                    log::debug!("Reconnecting to {:?} after error on reused connection - {:?}", self.address(), error);
+                    if let Some(s) = cached.take() {
+                        self.close(s);
+                    }
+                }
+            }
+        }
+
+        let mut s = self.connect()?;
+        let res = self.exchange(&mut s, packet);
+        match res {
+            Ok(_) => *cached = Some(s),
+            Err(_) => self.close(s),
+        }
+        res
+    }
+
+    fn begin_session(&self) {
+        if self.reuse {
+            *self.session.lock().unwrap() = Some(None);
+        }
+    }
+
+    fn end_session(&self) {
+        if let Some(Some(s)) = self.session.lock().unwrap().take() {
+            self.close(s);
+        }
+    }
+}
+
+// Checksum-correct response frame for `cmd` wrapping `payload`
+pub(crate) fn frame(cmd: u8, payload: &[u8]) -> Vec<u8> {
+    let mut body = vec![cmd];
+    if SensorGateway::has_wide_size(cmd) {
+        body.extend_from_slice(&(payload.len() as u16 + 4).to_be_bytes());
+    } else {
+        body.push(payload.len() as u8 + 3);
+    }
+    body.extend_from_slice(payload);
+
+    let mut frame = vec![0xFF, 0xFF];
+    frame.extend_from_slice(&body);
+    frame.push(SensorGateway::generate_checksum(&body));
+    frame
+}
+
+// Transport returning canned responses per command id, for testing without a gateway
+#[cfg(any(test, feature = "mock"))]
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: Mutex<HashMap<u8, Vec<u8>>>,
+    sent: Mutex<Vec<Vec<u8>>>,
+    // Time taken by each exchange
+    delay: Mutex<Duration>,
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Respond to `cmd` with a checksum-correct frame wrapping `payload`
+    pub fn respond(&self, cmd: u8, payload: &[u8]) {
+        self.respond_raw(cmd, Self::frame(cmd, payload));
+    }
+
+    // Respond to `cmd` with `frame` as-is, e.g. to simulate corrupt responses
+    pub fn respond_raw(&self, cmd: u8, frame: Vec<u8>) {
+        self.responses.lock().unwrap().insert(cmd, frame);
+    }
+
+    // Answer every command after `delay`, like a slow gateway
+    pub fn set_delay(&self, delay: Duration) {
+        *self.delay.lock().unwrap() = delay;
+    }
+
+    // All packets sent through the transport so far
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    pub fn frame(cmd: u8, payload: &[u8]) -> Vec<u8> {
+        frame(cmd, payload)
+    }
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl Transport for MockTransport {
+    fn send_and_receive(&self, packet: &[u8]) -> Result<Vec<u8>, Error> {
+        self.sent.lock().unwrap().push(packet.to_vec());
+        std::thread::sleep(*self.delay.lock().unwrap());
+
+        let cmd = packet.get(2).copied().unwrap_or(0);
+        match self.responses.lock().unwrap().get(&cmd) {
+            Some(frame) => Ok(frame.clone()),
+            None => Err(Error::new(ErrorKind::TimedOut, format!("No response for command {:#x}", cmd))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Listener answering the first packet on one connection with `response`, written in chunks
+    // of `chunk` bytes, then closing the connection
+    fn serve_once(response: Vec<u8>, chunk: usize) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut packet = [0u8; 64];
+            let _ = stream.read(&mut packet).unwrap();
+            for part in response.chunks(chunk) {
+                stream.write_all(part).unwrap();
+                stream.flush().unwrap();
+                thread::sleep(Duration::from_millis(2));
+            }
+        });
+        address
+    }
+
+    #[test_log::test]
+    fn reads_response_sent_in_chunks() {
+        let response = frame(0x27, &[0x01, 0x00, 0xE6, 0x06, 0x37, 0x08, 0x27, 0x8F, 0x0A, 0x00, 0xB4]);
+        let transport = TcpTransport::new(serve_once(response.clone(), 3), Duration::from_secs(2));
+
+        assert_eq!(transport.send_and_receive(&[0xFF, 0xFF, 0x27, 0x03, 0x2A]).unwrap(), response);
+    }
+
+    #[test_log::test]
+    fn reports_connection_closed_early() {
+        let response = frame(0x27, &[0x01, 0x00, 0xE6, 0x06, 0x37]);
+        let transport = TcpTransport::new(serve_once(response[..6].to_vec(), 2), Duration::from_secs(2));
+
+        let err = transport.send_and_receive(&[0xFF, 0xFF, 0x27, 0x03, 0x2A]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test_log::test]
+    fn reads_large_response_up_to_max() {
+        let payload: Vec<u8> = (0..6000u32).map(|i| i as u8).collect();
+        let response = frame(0x27, &payload);
+        assert!(response.len() > 4096);
+
+        // Larger than the default
+        let transport = TcpTransport::new(serve_once(response.clone(), 1500), Duration::from_secs(2));
+        let err = transport.send_and_receive(&[0xFF, 0xFF, 0x27, 0x03, 0x2A]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), format!("Response of {} bytes is larger than max_response_bytes 4096", response.len()));
+
+        let transport = TcpTransport::new(serve_once(response.clone(), 1500), Duration::from_secs(2)).max_response_bytes(8192);
+        assert_eq!(transport.send_and_receive(&[0xFF, 0xFF, 0x27, 0x03, 0x2A]).unwrap(), response);
+    }
+}